@@ -1,6 +1,61 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
 use gtm::parse::*;
 
+/// Generates `count` notes with a deterministic pseudo-random number of files and timeline entries.
+fn synthetic_corpus(count: usize) -> Vec<String> {
+    let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut next = |bound: u64| {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        seed % bound
+    };
+
+    (0..count)
+        .map(|_| {
+            let mut total = 0;
+            let mut lines = Vec::new();
+            for file in 0..1 + next(20) {
+                let mut time_spent = 0;
+                let mut timeline = String::new();
+                let mut epoch = 1_585_861_200 + next(1000) * 3600;
+                for _ in 0..1 + next(8) {
+                    let seconds = 10 + next(3600);
+                    time_spent += seconds;
+                    timeline.push_str(&format!(",{}:{}", epoch, seconds));
+                    epoch += 3600 * (1 + next(24));
+                }
+                total += time_spent;
+                lines.push(format!(
+                    "src/module{}/file{}.ts:{}{},{}",
+                    next(50),
+                    file,
+                    time_spent,
+                    timeline,
+                    ["r", "m", "d"][next(3) as usize]
+                ));
+            }
+            format!("[ver:1,total:{}]\n{}", total, lines.join("\n"))
+        })
+        .collect()
+}
+
+#[derive(Default)]
+struct Totals {
+    total: u64,
+    timeline: u64,
+}
+
+impl NoteVisitor<'_> for Totals {
+    fn header(&mut self, _version: u32, total: u32) {
+        self.total += total as u64;
+    }
+
+    fn timeline(&mut self, _epoch: i64, seconds: u32) {
+        self.timeline += seconds as u64;
+    }
+}
+
 fn criterion_benchmark(c: &mut Criterion) {
     let message = "[ver:1,total:4037]";
     assert!(parse_commit_note(message).is_ok());
@@ -22,6 +77,35 @@ fn criterion_benchmark(c: &mut Criterion) {
     c.bench_function("parse commit note", |b| {
         b.iter(|| parse_commit_note(black_box(message)))
     });
+
+    let corpus = synthetic_corpus(20_000);
+    let bytes = corpus.iter().map(|note| note.len() as u64).sum();
+    let mut totals = Totals::default();
+    for note in &corpus {
+        visit_commit_note(note, &mut totals).unwrap();
+    }
+    assert_eq!(totals.total, totals.timeline);
+
+    let mut group = c.benchmark_group("synthetic corpus");
+    group.throughput(Throughput::Bytes(bytes));
+    group.sample_size(10);
+    group.bench_function("parse_commit_note", |b| {
+        b.iter(|| {
+            for note in &corpus {
+                black_box(parse_commit_note(black_box(note)).unwrap());
+            }
+        })
+    });
+    group.bench_function("visit_commit_note", |b| {
+        b.iter(|| {
+            let mut totals = Totals::default();
+            for note in &corpus {
+                visit_commit_note(black_box(note), &mut totals).unwrap();
+            }
+            black_box(totals.total)
+        })
+    });
+    group.finish();
 }
 
 criterion_group!(benches, criterion_benchmark);
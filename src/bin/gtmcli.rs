@@ -7,14 +7,22 @@ extern crate serde_derive;
 extern crate serde_json;
 
 use ansi_term::{ANSIString, Colour::Red};
-use chrono::{Duration, NaiveDate};
+use chrono::{NaiveDate, Utc};
+use env_logger::Env;
+use git2::Repository;
+#[cfg(target_os = "linux")]
+use gtm::watch::Watcher;
 use gtm::{
-    epoch,
-    projects::Projects,
-    services::{write_commits, write_project_list, write_workdir_status},
-    NotesFilter,
+    commit::{commit_pending_note, CommitNoteError},
+    fsck::{check_project, Issue},
+    projects::{ProjectKey, Projects},
+    services::{write_commits, write_people, write_project_list, write_workdir_status},
+    status::{
+        record_app_event, record_event, EventFileError, RecordError, TimelineConfig, TimelineError,
+    },
+    AppTime, DateZone, NoteError, NotesFilter,
 };
-use io::BufWriter;
+use io::{BufWriter, Write};
 use std::{
     fmt::Display,
     io,
@@ -33,19 +41,142 @@ use structopt::StructOpt;
 enum Args {
     /// Returns commits with gtm time data
     Commits {
+        /// Returns only commits from this day on, as YYYY-MM-DD
         #[structopt(short, long)]
         from_date: Option<String>,
+        /// Returns only commits until this day, inclusive, as YYYY-MM-DD
         #[structopt(short, long)]
         to_date: Option<String>,
+        /// Time zone in which days start and end: local, utc, or an IANA name, e.g., Europe/Madrid
+        #[structopt(long, default_value = "utc")]
+        tz: DateZone,
+        /// Applies the dates to the time spent instead of the commit date,
+        /// trimming each note to the time spent within them
+        #[structopt(long)]
+        by_activity: bool,
         #[structopt(short, long)]
         message: Option<String>,
+        /// Recovers the valid files of partially corrupt notes
+        #[structopt(long)]
+        lenient: bool,
+        /// Leaves out the time spent in applications, e.g., the terminal
+        #[structopt(long)]
+        exclude_apps: bool,
+        /// Returns only commits whose author name or email contains this text
+        #[structopt(long)]
+        author: Option<String>,
+        /// Returns only commits with time spent in files matching this git pathspec, e.g., src/**
+        #[structopt(long = "path")]
+        paths: Vec<String>,
+        /// Removes the files not matching --path from the notes
+        #[structopt(long, requires = "paths")]
+        trim_paths: bool,
+        /// Returns only commits reachable from this revision, or within this range, e.g., main..feature
+        #[structopt(long)]
+        revisions: Option<String>,
+        /// Returns only commits whose total time is at least this number of seconds
+        #[structopt(long)]
+        min_total: Option<u32>,
+        /// Adds the parents, branches and tags of each commit, which takes longer
+        #[structopt(long)]
+        graph: bool,
+        /// Adds the first-parent distance of each commit from this branch, implies --graph
+        #[structopt(long)]
+        base: Option<String>,
+    },
+
+    /// Returns the time spent by each person, as resolved through the .mailmap of each project
+    People {
+        /// Counts only commits from this day on, as YYYY-MM-DD
+        #[structopt(short, long)]
+        from_date: Option<String>,
+        /// Counts only commits until this day, inclusive, as YYYY-MM-DD
+        #[structopt(short, long)]
+        to_date: Option<String>,
+        /// Time zone in which days start and end: local, utc, or an IANA name, e.g., Europe/Madrid
+        #[structopt(long, default_value = "utc")]
+        tz: DateZone,
+        /// Applies the dates to the time spent instead of the commit date
+        #[structopt(long)]
+        by_activity: bool,
+        /// Leaves out the time spent in applications, e.g., the terminal
+        #[structopt(long)]
+        exclude_apps: bool,
+        /// Counts only commits reachable from this revision, or within this range, e.g., main..feature
+        #[structopt(long)]
+        revisions: Option<String>,
     },
 
     /// Returns the init(ialized) projects by gtm
     Projects,
 
     /// Returns the uncommited gtm data
-    Status,
+    Status {
+        /// Leaves out the time spent in applications, e.g., the terminal
+        #[structopt(long)]
+        exclude_apps: bool,
+        /// Width in seconds of the bins in which events are grouped
+        #[structopt(long, default_value = "60")]
+        bin_width: u32,
+        /// Seconds credited to each bin with events
+        #[structopt(long, default_value = "60")]
+        credit: u32,
+        /// Credits each bin with the time until the next bin with events,
+        /// when it does not exceed this number of seconds
+        #[structopt(long)]
+        idle_timeout: Option<u32>,
+    },
+
+    /// Records an event for a file of an init(ialized) project
+    ///
+    /// Duplicate events for the same file within the same second are ignored.
+    Record {
+        /// Path of the file to record,
+        /// or any path within the project when recording an application
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+        /// Records time spent in an application, e.g., terminal, instead of a file
+        #[structopt(long)]
+        app: Option<String>,
+    },
+
+    /// Watches all init(ialized) projects and records file events
+    ///
+    /// Files ignored by git, and files within .git/ and .gtm/, are skipped.
+    /// Runs until interrupted.
+    #[cfg(target_os = "linux")]
+    Watch,
+
+    /// Commits the pending gtm data as a note on HEAD
+    ///
+    /// Pending events are merged with the note already on HEAD, if any,
+    /// and then archived.
+    /// Returns the note written, or null when there is no pending data.
+    CommitNote {
+        /// Path within the git repository, defaults to the current directory
+        #[structopt(parse(from_os_str))]
+        path: Option<PathBuf>,
+        /// Width in seconds of the bins in which events are grouped
+        #[structopt(long, default_value = "60")]
+        bin_width: u32,
+        /// Seconds credited to each bin with events
+        #[structopt(long, default_value = "60")]
+        credit: u32,
+        /// Credits each bin with the time until the next bin with events,
+        /// when it does not exceed this number of seconds
+        #[structopt(long)]
+        idle_timeout: Option<u32>,
+    },
+
+    /// Verifies the gtm notes of all init(ialized) projects
+    ///
+    /// Exits with a non-zero code when any problem is found.
+    #[structopt(alias = "verify")]
+    Fsck {
+        /// Reports problems in JSON format instead of a table
+        #[structopt(long)]
+        json: bool,
+    },
 }
 
 struct Tty<'a>(ANSIString<'a>);
@@ -84,6 +215,11 @@ enum GtmError {
     Git(git2::Error),
     Parse(chrono::ParseError, String),
     Io(io::Error, PathBuf),
+    Note(NoteError),
+    Events(TimelineError),
+    EventFiles(EventFileError),
+    Record(RecordError),
+    Fsck(usize),
 }
 
 impl Termination for GtmResult<GtmError> {
@@ -116,6 +252,11 @@ impl Display for GtmError {
             GtmError::Git(err) => write!(f, "Git2 error: {}", err),
             GtmError::Parse(err, field) => write!(f, "Could not parse {} argument: {}", field, err),
             GtmError::Io(err, path) => write!(f, "Io error {:?} : {}", path, err),
+            GtmError::Note(err) => write!(f, "{}", err),
+            GtmError::Events(err) => write!(f, "Could not read pending events: {}", err),
+            GtmError::EventFiles(err) => write!(f, "Could not read pending events: {}", err),
+            GtmError::Record(err) => write!(f, "Could not record event: {}", err),
+            GtmError::Fsck(count) => write!(f, "Found {} problem(s) in gtm notes", count),
         }
     }
 }
@@ -132,61 +273,235 @@ impl From<git2::Error> for GtmError {
     }
 }
 
+impl From<RecordError> for GtmError {
+    fn from(err: RecordError) -> Self {
+        GtmError::Record(err)
+    }
+}
+
+impl From<CommitNoteError> for GtmError {
+    fn from(err: CommitNoteError) -> Self {
+        match err {
+            CommitNoteError::Git(err) => GtmError::Git(err),
+            CommitNoteError::Io(err, path) => GtmError::Io(err, path),
+            CommitNoteError::EventFiles(err) => GtmError::EventFiles(err),
+            CommitNoteError::Events(err) => GtmError::Events(err),
+            CommitNoteError::Note(err) => GtmError::Note(err),
+        }
+    }
+}
+
 fn from_config() -> Result<Projects, GtmError> {
     let path = Projects::config_path().unwrap();
     Projects::from_file(&path).map_err(|e| GtmError::Io(e, path))
 }
 
-fn parse_arg_date(
-    date: &Option<String>,
-    field: &str,
-    days: i64,
-) -> Result<Option<epoch>, GtmError> {
-    Ok(match date {
-        None => None,
-        Some(date) => Some(
+fn app_time(exclude_apps: bool) -> AppTime {
+    if exclude_apps {
+        AppTime::Exclude
+    } else {
+        AppTime::Include
+    }
+}
+
+fn parse_arg_date(date: &Option<String>, field: &str) -> Result<Option<NaiveDate>, GtmError> {
+    date.as_ref()
+        .map(|date| {
             NaiveDate::parse_from_str(date, "%Y-%m-%d")
-                .map_err(|e| GtmError::Parse(e, field.to_owned()))?
-                .checked_add_signed(Duration::days(days))
-                .unwrap()
-                .and_hms(0, 0, 0)
-                .timestamp(),
-        ),
-    })
+                .map_err(|e| GtmError::Parse(e, field.to_owned()))
+        })
+        .transpose()
+}
+
+fn write_issues_table<W: Write>(mut writer: W, issues: &[Issue]) -> io::Result<()> {
+    if issues.is_empty() {
+        return Ok(());
+    }
+    let width = issues
+        .iter()
+        .map(|i| i.project.len())
+        .fold("PROJECT".len(), usize::max);
+    writeln!(
+        writer,
+        "{:width$}  {:7}  PROBLEM",
+        "PROJECT",
+        "COMMIT",
+        width = width
+    )?;
+    for issue in issues {
+        writeln!(
+            writer,
+            "{:width$}  {:7}  {}",
+            issue.project,
+            issue.commit.get(..7).unwrap_or(&issue.commit),
+            issue.problem,
+            width = width
+        )?;
+    }
+    Ok(())
 }
 
 fn main() -> GtmResult<GtmError> {
+    env_logger::Builder::from_env(Env::default().default_filter_or("warn")).init();
     let command = Args::from_args();
 
     match command {
         Args::Commits {
             from_date,
             to_date,
+            tz,
+            by_activity,
             message,
+            lenient,
+            exclude_apps,
+            author,
+            paths,
+            trim_paths,
+            revisions,
+            min_total,
+            graph,
+            base,
         } => {
-            let from = parse_arg_date(&from_date, "from", 0)?;
-            let to = parse_arg_date(&to_date, "to", 1)?;
+            let mut filter = NotesFilter::all();
+            filter.days(
+                parse_arg_date(&from_date, "from")?,
+                parse_arg_date(&to_date, "to")?,
+                tz,
+            );
+            filter.needle = message;
+            filter.paths(paths, trim_paths);
+            if by_activity {
+                filter.by_activity();
+            }
+            if lenient {
+                filter.lenient();
+            }
+            if exclude_apps {
+                filter.exclude_apps();
+            }
+            if let Some(author) = author {
+                filter.author(author);
+            }
+            if let Some(revisions) = revisions {
+                filter.revisions(revisions);
+            }
+            if let Some(min_total) = min_total {
+                filter.min_total(min_total);
+            }
+            if graph || base.is_some() {
+                filter.graph(base);
+            }
             let out = std::io::stdout();
             let mut writer = BufWriter::with_capacity(1024 * 1024, out);
-            write_commits(
-                &mut writer,
-                from_config()?.keys(),
-                &NotesFilter {
-                    from,
-                    to,
-                    needle: message,
-                },
-            )?;
+            write_commits(&mut writer, from_config()?.keys(), &filter)?;
+        }
+        Args::People {
+            from_date,
+            to_date,
+            tz,
+            by_activity,
+            exclude_apps,
+            revisions,
+        } => {
+            let mut filter = NotesFilter::all();
+            filter.days(
+                parse_arg_date(&from_date, "from")?,
+                parse_arg_date(&to_date, "to")?,
+                tz,
+            );
+            if by_activity {
+                filter.by_activity();
+            }
+            if exclude_apps {
+                filter.exclude_apps();
+            }
+            if let Some(revisions) = revisions {
+                filter.revisions(revisions);
+            }
+            let out = std::io::stdout();
+            let mut writer = BufWriter::new(out);
+            write_people(&mut writer, from_config()?.keys(), &filter)?;
+            writer.flush().expect("Could not flush people");
         }
         Args::Projects => {
             let out = std::io::stdout();
             let writer = BufWriter::with_capacity(1024 * 1024, out);
             write_project_list(writer, &from_config()?);
         }
-        Args::Status => {
+        Args::Status {
+            exclude_apps,
+            bin_width,
+            credit,
+            idle_timeout,
+        } => {
             let out = std::io::stdout();
             let mut writer = BufWriter::with_capacity(1024 * 1024, out);
-            write_workdir_status(&mut writer, from_config()?.keys());
+            write_workdir_status(
+                &mut writer,
+                from_config()?.keys(),
+                app_time(exclude_apps),
+                TimelineConfig {
+                    bin_width,
+                    credit,
+                    idle_timeout,
+                },
+            );
+        }
+        Args::Record { file, app: None } => {
+            record_event(&from_config()?, &file, Utc::now().timestamp())?;
+        }
+        Args::Record {
+            file,
+            app: Some(app),
+        } => {
+            record_app_event(&from_config()?, &file, &app, Utc::now().timestamp())?;
+        }
+        #[cfg(target_os = "linux")]
+        Args::Watch => {
+            let mut watcher = Watcher::from_projects(&from_config()?)?;
+            loop {
+                for (workdir, event) in watcher.record_events()? {
+                    log::info!("Recorded {} in {:?}", event.filename(), workdir);
+                }
+            }
+        }
+        Args::CommitNote {
+            path,
+            bin_width,
+            credit,
+            idle_timeout,
+        } => {
+            let repo = Repository::discover(path.unwrap_or_else(|| PathBuf::from(".")))?;
+            let config = TimelineConfig {
+                bin_width,
+                credit,
+                idle_timeout,
+            };
+            let (note, skipped) = commit_pending_note(&repo, config)?;
+            for err in skipped {
+                log::warn!("Skipping event: {}", err);
+            }
+            let out = std::io::stdout();
+            let mut writer = BufWriter::new(out);
+            serde_json::to_writer(&mut writer, &note).expect("Could not serialize note");
+            writer.flush().expect("Could not flush note");
+        }
+        Args::Fsck { json } => {
+            let mut issues = Vec::new();
+            for path in from_config()?.keys() {
+                issues.extend(check_project(path, path.key())?);
+            }
+            let out = std::io::stdout();
+            let mut writer = BufWriter::new(out);
+            if json {
+                serde_json::to_writer(&mut writer, &issues).expect("Could not serialize issues");
+            } else {
+                write_issues_table(&mut writer, &issues).expect("Could not write issues");
+            }
+            writer.flush().expect("Could not flush issues");
+            if !issues.is_empty() {
+                return GtmResult(Err(GtmError::Fsck(issues.len())));
+            }
         }
     };
 
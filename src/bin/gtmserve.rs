@@ -102,19 +102,27 @@ async fn dispatch(
                 let mut out = Vec::new();
                 let mut ser = serde_json::Serializer::new(&mut out);
                 let mut seq = ser.serialize_seq(None).unwrap();
+                let filter = NotesFilter::all();
                 for ghrepo in repos {
                     let url_path = url_path(&ghrepo.clone_url);
                     if let Ok(repo) = Repository::open(format!("{}/{}", args.datadir, url_path)) {
-                        if let Err(_err) = get_notes(
-                            |c| {
-                                seq.serialize_element(&c.commit)
-                                    .expect("Could not serialize commit");
-                            },
-                            &repo,
-                            &ghrepo.full_name,
-                            &NotesFilter::all(),
-                        ) {
-                            debug!("No gtm-data for repo {}", ghrepo.full_name);
+                        match get_notes(&repo, &ghrepo.full_name, &filter) {
+                            Ok(notes) => {
+                                for note in notes {
+                                    match note {
+                                        Ok(note) => seq
+                                            .serialize_element(&note.commit)
+                                            .expect("Could not serialize commit"),
+                                        Err(err) => {
+                                            warn!(
+                                                "Skipping note in {}: {:#}",
+                                                ghrepo.full_name, err
+                                            )
+                                        }
+                                    }
+                                }
+                            }
+                            Err(_err) => debug!("No gtm-data for repo {}", ghrepo.full_name),
                         }
                     } else {
                         warn!("Could not open repo {}", ghrepo.full_name);
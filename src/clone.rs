@@ -45,16 +45,13 @@ mod tests {
 
         println!("Cloning remote `{}` into `{:?}`", url, path);
         let repo = clone_repo(url, path)?;
+        let filter = NotesFilter::all();
         let mut notes = Vec::new();
-        get_notes(
-            |c| {
-                println!("{:?}", c.commit);
-                notes.push(c.commit);
-            },
-            &repo,
-            "sdfsdf",
-            &NotesFilter::all(),
-        )?;
+        for note in get_notes(&repo, "sdfsdf", &filter)? {
+            let note = note?;
+            println!("{:?}", note.commit);
+            notes.push(note);
+        }
         assert_ne!(notes.len(), 0);
 
         Ok(())
@@ -0,0 +1,247 @@
+use crate::{
+    parse::{parse_commit_note, ESCAPED_PATHS_VERSION},
+    status::{read_event_files_lenient, EventFileError, Timeline, TimelineConfig, TimelineError},
+    CommitNote, FileNote, FileStatus, NoteError, GTM_REFS,
+};
+use git2::{Delta, ErrorCode, Repository};
+use log::warn;
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Represents the errors that prevent committing the pending gtm data.
+#[derive(Debug)]
+pub enum CommitNoteError {
+    /// Occurs when git cannot read HEAD or write the note.
+    Git(git2::Error),
+
+    /// Occurs when the event files at the given path cannot be archived.
+    Io(io::Error, PathBuf),
+
+    /// Occurs when the `.gtm` directory cannot be read.
+    EventFiles(EventFileError),
+
+    /// Occurs when the pending events cannot be turned into a timeline.
+    Events(TimelineError),
+
+    /// Occurs when the note already attached to HEAD cannot be read,
+    /// in which case it is left untouched.
+    Note(NoteError),
+}
+
+impl fmt::Display for CommitNoteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommitNoteError::Git(err) => write!(f, "Git2 error: {}", err),
+            CommitNoteError::Io(err, path) => write!(f, "Io error {:?} : {}", path, err),
+            CommitNoteError::EventFiles(err) => write!(f, "{}", err),
+            CommitNoteError::Events(err) => write!(f, "{}", err),
+            CommitNoteError::Note(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for CommitNoteError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CommitNoteError::Git(err) => Some(err),
+            CommitNoteError::Io(err, _) => Some(err),
+            CommitNoteError::EventFiles(err) => Some(err),
+            CommitNoteError::Events(err) => Some(err),
+            CommitNoteError::Note(err) => Some(err),
+        }
+    }
+}
+
+impl From<git2::Error> for CommitNoteError {
+    fn from(err: git2::Error) -> Self {
+        CommitNoteError::Git(err)
+    }
+}
+
+/// Commits the pending events of the working directory of `repo`
+/// as a gtm note attached to HEAD under `GTM_REFS`.
+///
+/// The events in `.gtm` are turned into a `CommitNote` by `Timeline::commit_note`,
+/// attributing time according to `config`,
+/// with the status of each file set by `mark_commit_status`,
+/// and merged with the note already attached to HEAD, if any.
+/// The note is written with `ESCAPED_PATHS_VERSION` when any of its paths
+/// cannot be written as it is, see `needs_escaped_path`.
+/// The consumed event files are moved to `.gtm/archive/<commit>` before the note is written,
+/// so that they are not committed twice.
+/// When either step fails, the event files are moved back and stay pending.
+///
+/// Returns the note written, or `None` when there are no pending events,
+/// together with an error for each event file skipped because it cannot be read.
+/// Skipped event files are left in `.gtm`.
+pub fn commit_pending_note(
+    repo: &Repository,
+    config: TimelineConfig,
+) -> Result<(Option<CommitNote<'static>>, Vec<EventFileError>), CommitNoteError> {
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| git2::Error::from_str("Cannot commit gtm data in a bare repository"))?;
+    let (event_files, skipped) =
+        read_event_files_lenient(workdir).map_err(CommitNoteError::EventFiles)?;
+    let (mut paths, events): (Vec<_>, Vec<_>) = event_files.into_iter().unzip();
+    if events.is_empty() {
+        return Ok((None, skipped));
+    }
+
+    let head = repo.head()?.peel_to_commit()?;
+    let mut pending = Timeline::from_events_with(&events, config)
+        .map_err(CommitNoteError::Events)?
+        .commit_note()
+        .into_owned();
+    mark_commit_status(repo, &head, &mut pending)?;
+    let mut note = match repo.find_note(Some(GTM_REFS), head.id()) {
+        Err(err) if err.code() == ErrorCode::NotFound => pending,
+        Err(err) => return Err(err.into()),
+        Ok(note) => {
+            let oid = head.id();
+            let message = note
+                .message()
+                .ok_or_else(|| CommitNoteError::Note(NoteError::InvalidUtf8 { oid }))?;
+            let mut note = parse_commit_note(message)
+                .map_err(|err| CommitNoteError::Note(NoteError::Parse { oid, err }))?
+                .into_owned();
+            note.merge(pending);
+            note
+        }
+    };
+
+    if note.files.iter().any(needs_escaped_path) {
+        note.version = note.version.max(ESCAPED_PATHS_VERSION);
+    }
+
+    let sig = repo.signature()?;
+    paths.dedup();
+    let archive = workdir
+        .join(".gtm")
+        .join("archive")
+        .join(head.id().to_string());
+    let archived = archive_event_files(&paths, &archive)?;
+    let message = note.to_string();
+    if let Err(err) = repo.note(&sig, &sig, Some(GTM_REFS), head.id(), &message, true) {
+        restore_event_files(&archived);
+        return Err(err.into());
+    }
+
+    Ok((Some(note), skipped))
+}
+
+/// Sets the status of each file in `note` from the changes introduced by `commit`,
+/// *i.e.*, its diff against its first parent.
+///
+/// A file changed by the commit is `FileStatus::Modified`,
+/// a file deleted by the commit is `FileStatus::Deleted`,
+/// and any other file is `FileStatus::Read`.
+/// Applications, see `FileNote::app`, are left untouched.
+pub fn mark_commit_status(
+    repo: &Repository,
+    commit: &git2::Commit,
+    note: &mut CommitNote,
+) -> Result<(), git2::Error> {
+    let parent_tree = match commit.parent(0) {
+        Ok(parent) => Some(parent.tree()?),
+        Err(err) if err.code() == ErrorCode::NotFound => None,
+        Err(err) => return Err(err),
+    };
+    let diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), None)?;
+    let mut statuses = HashMap::new();
+    for delta in diff.deltas() {
+        let (file, status) = match delta.status() {
+            Delta::Deleted => (delta.old_file(), FileStatus::Deleted),
+            Delta::Unmodified => continue,
+            _ => (delta.new_file(), FileStatus::Modified),
+        };
+        if let Some(path) = file.path_bytes() {
+            statuses.insert(path.to_owned(), status);
+        }
+    }
+
+    for file in note.files.iter_mut().filter(|file| file.app().is_none()) {
+        file.status = statuses
+            .get(file.path_bytes())
+            .cloned()
+            .unwrap_or(FileStatus::Read);
+    }
+    Ok(())
+}
+
+/// Returns whether the path of `file` cannot be written as it is in notes
+/// before `ESCAPED_PATHS_VERSION`,
+/// *i.e.*, it contains `,`, `:`, control characters or bytes that are not valid UTF-8.
+fn needs_escaped_path(file: &FileNote) -> bool {
+    file.raw_path.is_some()
+        || file
+            .source_file
+            .contains(|c: char| c == ',' || c == ':' || c.is_control())
+}
+
+/// Moves the event files at `paths` into the `archive` directory.
+/// Returns the original and archived path of each event file.
+///
+/// Event files already archived are never overwritten,
+/// *e.g.*, when committing twice on the same HEAD:
+/// an event file whose name is taken is archived as `<name>.<n>` instead.
+/// When an event file cannot be moved, the ones already moved are put back,
+/// so that either all or none of the event files are archived.
+fn archive_event_files(
+    paths: &[PathBuf],
+    archive: &Path,
+) -> Result<Vec<(PathBuf, PathBuf)>, CommitNoteError> {
+    fs::create_dir_all(archive).map_err(|err| CommitNoteError::Io(err, archive.to_owned()))?;
+    let mut archived = Vec::with_capacity(paths.len());
+    for path in paths {
+        let file_name = path
+            .file_name()
+            .expect("Event file already read")
+            .to_owned();
+        let mut index = 0;
+        let result = loop {
+            let mut target_name = file_name.clone();
+            if index > 0 {
+                target_name.push(format!(".{}", index));
+            }
+            let target = archive.join(target_name);
+            match move_new(path, &target) {
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => index += 1,
+                result => break result.map(|()| target),
+            }
+        };
+        match result {
+            Ok(target) => archived.push((path.clone(), target)),
+            Err(err) => {
+                restore_event_files(&archived);
+                return Err(CommitNoteError::Io(err, path.clone()));
+            }
+        }
+    }
+    Ok(archived)
+}
+
+/// Moves archived event files back to their original paths.
+/// Event files that cannot be moved back,
+/// *e.g.*, because a new event file took their name, are logged and left in the archive.
+fn restore_event_files(archived: &[(PathBuf, PathBuf)]) {
+    for (path, target) in archived {
+        if let Err(err) = move_new(target, path) {
+            warn!("Could not restore event file {:?}: {}", path, err);
+        }
+    }
+}
+
+/// Moves the file at `from` to `to`, failing with `io::ErrorKind::AlreadyExists`
+/// instead of overwriting `to` when it exists.
+fn move_new(from: &Path, to: &Path) -> io::Result<()> {
+    fs::hard_link(from, to)?;
+    if let Err(err) = fs::remove_file(from) {
+        let _ = fs::remove_file(to);
+        return Err(err);
+    }
+    Ok(())
+}
@@ -0,0 +1,196 @@
+use crate::{
+    parse::{parse_commit_note_lenient, CommitNoteParseError},
+    seconds, CommitNote, GTM_REFS,
+};
+use git2::{ErrorCode, Repository};
+use serde::Serialize;
+use std::{collections::HashMap, fmt, path::Path};
+
+/// The kinds of problems detected by `check_notes`.
+#[derive(PartialEq, Debug, Serialize)]
+#[serde(tag = "Kind")]
+pub enum Problem {
+    /// The repository of the project cannot be opened,
+    /// e.g., because it was moved or deleted.
+    #[serde(rename_all = "PascalCase")]
+    MissingRepository { error: String },
+
+    /// The note is attached to a commit that does not exist in the repository.
+    MissingCommit,
+
+    /// The note text is not valid UTF-8.
+    InvalidUtf8,
+
+    /// The note header or one of its lines cannot be parsed.
+    #[serde(rename_all = "PascalCase")]
+    InvalidNote { error: String },
+
+    /// The header `total` is not the sum of the time spent in each file.
+    #[serde(rename_all = "PascalCase")]
+    TotalMismatch { total: seconds, files: seconds },
+
+    /// The sum of the timeline entries of `file` is not its `time_spent`.
+    #[serde(rename_all = "PascalCase")]
+    TimelineMismatch {
+        file: String,
+        time_spent: seconds,
+        timeline: seconds,
+    },
+
+    /// The file `file` appears more than once in the note.
+    #[serde(rename_all = "PascalCase")]
+    DuplicatedFile { file: String },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::MissingRepository { error } => {
+                write!(f, "repository cannot be opened: {}", error)
+            }
+            Problem::MissingCommit => write!(f, "note attached to a missing commit"),
+            Problem::InvalidUtf8 => write!(f, "note is not valid UTF-8"),
+            Problem::InvalidNote { error } => write!(f, "invalid note: {}", error),
+            Problem::TotalMismatch { total, files } => {
+                write!(f, "header total is {} but files add up to {}", total, files)
+            }
+            Problem::TimelineMismatch {
+                file,
+                time_spent,
+                timeline,
+            } => write!(
+                f,
+                "`{}` time spent is {} but its timeline adds up to {}",
+                file, time_spent, timeline
+            ),
+            Problem::DuplicatedFile { file } => write!(f, "`{}` appears more than once", file),
+        }
+    }
+}
+
+/// A `Problem` found in the note attached to `commit` within `project`.
+#[derive(PartialEq, Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Issue {
+    pub project: String,
+    /// The commit of the note, empty for problems of the whole project.
+    pub commit: String,
+    pub problem: Problem,
+}
+
+/// Returns the consistency problems found in a parsed `CommitNote`.
+///
+/// ```
+/// use gtm::{fsck::*, parse::*};
+///
+/// let note = parse_commit_note("[ver:1,total:150]
+/// src/main.ts:150,1585861200:60,1585875600:90,r").unwrap();
+/// assert_eq!(check_commit_note(&note), vec![]);
+///
+/// let note = parse_commit_note("[ver:1,total:100]
+/// src/main.ts:60,1585861200:60,r
+/// src/main.ts:60,1585861200:50,r").unwrap();
+/// assert_eq!(check_commit_note(&note), vec![
+///     Problem::TotalMismatch { total: 100, files: 120 },
+///     Problem::TimelineMismatch { file: "src/main.ts".to_owned(), time_spent: 60, timeline: 50 },
+///     Problem::DuplicatedFile { file: "src/main.ts".to_owned() },
+/// ]);
+/// ```
+pub fn check_commit_note(note: &CommitNote) -> Vec<Problem> {
+    let mut problems = Vec::new();
+
+    let files = note.files.iter().map(|file| file.time_spent).sum();
+    if note.total != files {
+        problems.push(Problem::TotalMismatch {
+            total: note.total,
+            files,
+        });
+    }
+
+    let mut counts = HashMap::new();
+    for file in &note.files {
+        let timeline = file.timeline.values().sum();
+        if file.time_spent != timeline {
+            problems.push(Problem::TimelineMismatch {
+                file: file.source_file.to_string(),
+                time_spent: file.time_spent,
+                timeline,
+            });
+        }
+        *counts.entry(file.source_file.as_ref()).or_insert(0) += 1;
+    }
+
+    for file in &note.files {
+        if counts.remove(file.source_file.as_ref()).unwrap_or(0) > 1 {
+            problems.push(Problem::DuplicatedFile {
+                file: file.source_file.to_string(),
+            });
+        }
+    }
+
+    problems
+}
+
+fn invalid_note(err: CommitNoteParseError) -> Problem {
+    Problem::InvalidNote {
+        error: err.to_string(),
+    }
+}
+
+/// Checks every note under `GTM_REFS` in `repo`.
+///
+/// Unlike `get_notes`, it visits all notes regardless of whether
+/// their commits still exist or their text can be parsed.
+/// Every invalid line in a note is reported,
+/// and consistency checks only run on notes without invalid lines.
+/// A repository without gtm notes has no issues.
+pub fn check_notes(repo: &Repository, project: &str) -> Result<Vec<Issue>, git2::Error> {
+    let notes = match repo.notes(Some(GTM_REFS)) {
+        Err(err) if err.code() == ErrorCode::NotFound => return Ok(Vec::new()),
+        notes => notes?,
+    };
+
+    let mut issues = Vec::new();
+    for note_assoc in notes {
+        let (note_id, oid) = note_assoc?;
+        let issue = |problem| Issue {
+            project: project.to_owned(),
+            commit: oid.to_string(),
+            problem,
+        };
+
+        if repo.find_commit(oid).is_err() {
+            issues.push(issue(Problem::MissingCommit));
+        }
+
+        let blob = repo.find_blob(note_id)?;
+        let problems = match std::str::from_utf8(blob.content()) {
+            Err(_) => vec![Problem::InvalidUtf8],
+            Ok(message) => match parse_commit_note_lenient(message) {
+                Err(err) => vec![invalid_note(err)],
+                Ok((note, diagnostics)) if diagnostics.is_empty() => check_commit_note(&note),
+                Ok((_note, diagnostics)) => diagnostics.into_iter().map(invalid_note).collect(),
+            },
+        };
+        issues.extend(problems.into_iter().map(issue));
+    }
+
+    Ok(issues)
+}
+
+/// Checks every note of the repository at `path`, like `check_notes`.
+///
+/// A repository that cannot be opened is reported as a `Problem::MissingRepository`,
+/// so that the other projects can still be checked.
+pub fn check_project(path: &Path, project: &str) -> Result<Vec<Issue>, git2::Error> {
+    match Repository::open(path) {
+        Ok(repo) => check_notes(&repo, project),
+        Err(err) => Ok(vec![Issue {
+            project: project.to_owned(),
+            commit: String::new(),
+            problem: Problem::MissingRepository {
+                error: err.message().to_owned(),
+            },
+        }]),
+    }
+}
@@ -3,21 +3,32 @@
 #[macro_use]
 extern crate lazy_static;
 
-use chrono::{DateTime, FixedOffset, TimeZone};
-use git2::{Note, Repository};
-use parse::parse_commit_note;
+use chrono::{DateTime, Duration, FixedOffset, Local, NaiveDate, TimeZone, Utc};
+use chrono_tz::{Tz, TZ_VARIANTS};
+use git2::{ErrorCode, Mailmap, Oid, Pathspec, PathspecFlags, Repository, Revwalk, Sort};
+use parse::{CommitNoteParseError, NoteVersions, ParseMode};
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+    path::Path,
+    str::FromStr,
+};
 
 extern crate chrono;
 
 pub const GTM_REFS: &str = "refs/notes/gtm-data";
 
 pub mod clone;
+pub mod commit;
+pub mod fsck;
 pub mod parse;
 pub mod projects;
 pub mod services;
 pub mod status;
+#[cfg(target_os = "linux")]
+pub mod watch;
 pub mod oauth2;
 
 /// Represents a Unix epoch (timestamp), *i.e.*, number of non-leap
@@ -28,6 +39,76 @@ pub type epoch = i64;
 #[allow(non_camel_case_types)]
 type seconds = u32;
 
+/// The status of a file within a commit note.
+///
+/// It is written as a single letter in notes and serialized the same way.
+/// Statuses not known by this version are kept as `Unknown`,
+/// so that notes written by future gtm versions can still be parsed.
+///
+/// ```
+/// use gtm::*;
+/// assert_eq!(FileStatus::from("m"), FileStatus::Modified);
+/// assert_eq!(FileStatus::from("x"), FileStatus::Unknown("x".to_owned()));
+/// assert_eq!(serde_json::to_string(&FileStatus::Deleted).unwrap(), r#""d""#);
+/// assert_eq!(serde_json::from_str::<FileStatus>(r#""r""#).unwrap(), FileStatus::Read);
+/// ```
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum FileStatus {
+    /// The file was modified, written as `m`.
+    Modified,
+
+    /// The file was only read, written as `r`.
+    Read,
+
+    /// The file was deleted, written as `d`.
+    Deleted,
+
+    /// A status not recognized by this version.
+    Unknown(String),
+}
+
+impl FileStatus {
+    /// Returns the letter used to write this status.
+    pub fn as_str(&self) -> &str {
+        match self {
+            FileStatus::Modified => "m",
+            FileStatus::Read => "r",
+            FileStatus::Deleted => "d",
+            FileStatus::Unknown(status) => status,
+        }
+    }
+}
+
+impl From<&str> for FileStatus {
+    fn from(status: &str) -> Self {
+        match status {
+            "m" => FileStatus::Modified,
+            "r" => FileStatus::Read,
+            "d" => FileStatus::Deleted,
+            status => FileStatus::Unknown(status.to_owned()),
+        }
+    }
+}
+
+impl fmt::Display for FileStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl Serialize for FileStatus {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for FileStatus {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let status = String::deserialize(deserializer)?;
+        Ok(FileStatus::from(status.as_str()))
+    }
+}
+
 #[derive(PartialEq, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 /// Contains the access timeline of a file within a commit note.
@@ -44,10 +125,11 @@ type seconds = u32;
 /// use gtm::*;
 ///
 /// assert_eq!(serde_json::to_string(&FileNote {
-///         source_file: "src/main.ts",
+///         source_file: "src/main.ts".into(),
 ///         time_spent: 150,
 ///         timeline: btreemap! { 1585861200 => 60, 1585875600 => 90 },
-///         status: "r",
+///         status: FileStatus::Read,
+///         raw_path: None,
 ///     }).unwrap(),
 ///     r#"{"SourceFile":"src/main.ts","TimeSpent":150,"Timeline":{"1585861200":60,"1585875600":90},"Status":"r"}"#
 /// );
@@ -55,26 +137,34 @@ type seconds = u32;
 ///
 /// Note that when using deserialization in JSON format,
 /// timeline keys will be automatically converted from `&str` keys.
+/// The file path is always deserialized as an owned `String`,
+/// so that `FileNote<'static>` implements `DeserializeOwned`.
 ///
 /// ```
 /// #[macro_use] extern crate maplit;
 /// use gtm::*;
 ///
 /// assert_eq!(FileNote {
-///         source_file: "src/main.ts",
+///         source_file: "src/main.ts".into(),
 ///         time_spent: 150,
 ///         timeline: btreemap! { 1585861200 => 60, 1585875600 => 90 },
-///         status: "r",
+///         status: FileStatus::Read,
+///         raw_path: None,
 ///     },
 ///     serde_json::from_str(
 ///         r#"{"SourceFile":"src/main.ts","TimeSpent":150,"Timeline":{"1585861200":60,"1585875600":90},"Status":"r"}"#
 ///     ).unwrap());
 /// ```
 pub struct FileNote<'a> {
-    pub source_file: &'a str,
+    pub source_file: Cow<'a, str>,
     pub time_spent: seconds,
     pub timeline: BTreeMap<epoch, seconds>,
-    pub status: &'a str,
+    pub status: FileStatus,
+    /// The bytes of the file path when it is not valid UTF-8,
+    /// in which case `source_file` has the invalid bytes replaced with `U+FFFD`.
+    /// Notes are written with these bytes, so that the path is kept as it was.
+    #[serde(skip)]
+    pub raw_path: Option<Vec<u8>>,
 }
 
 #[derive(PartialEq, Debug, Serialize, Deserialize)]
@@ -82,10 +172,129 @@ pub struct FileNote<'a> {
 pub struct CommitNote<'a> {
     pub version: u32,
     pub total: seconds,
-    #[serde(borrow)]
     pub files: Vec<FileNote<'a>>,
 }
 
+impl FileNote<'_> {
+    /// Returns a `FileNote` that owns its file path,
+    /// and therefore does not borrow from the note text.
+    ///
+    /// ```
+    /// use gtm::{*, parse::*};
+    /// let note: FileNote<'static> = {
+    ///     let text = String::from("src/main.ts:60,1585861200:60,r");
+    ///     parse_file_note(&text).unwrap().into_owned()
+    /// };
+    /// assert_eq!(note.source_file, "src/main.ts");
+    /// ```
+    pub fn into_owned(self) -> FileNote<'static> {
+        FileNote {
+            source_file: Cow::Owned(self.source_file.into_owned()),
+            time_spent: self.time_spent,
+            timeline: self.timeline,
+            status: self.status,
+            raw_path: self.raw_path,
+        }
+    }
+
+    /// Returns the bytes of the file path,
+    /// *i.e.*, `raw_path` when set, or `source_file` otherwise.
+    ///
+    /// ```
+    /// use gtm::parse::*;
+    /// let note = parse_commit_note("[ver:3,total:60]\nsrc/%FF.rs:60,1585861200:60,r").unwrap();
+    /// assert_eq!(note.files[0].source_file, "src/\u{FFFD}.rs");
+    /// assert_eq!(note.files[0].path_bytes(), b"src/\xff.rs");
+    /// ```
+    pub fn path_bytes(&self) -> &[u8] {
+        match &self.raw_path {
+            Some(raw_path) => raw_path,
+            None => self.source_file.as_bytes(),
+        }
+    }
+
+    /// Returns the name of the application when this note holds
+    /// the time spent in an application, e.g., the terminal, instead of a file.
+    ///
+    /// Application time is recorded for the file `.gtm/<app>.app`.
+    ///
+    /// ```
+    /// use gtm::parse::*;
+    /// let note = parse_file_note(".gtm/terminal.app:60,1585861200:60,r").unwrap();
+    /// assert_eq!(note.app(), Some("terminal"));
+    /// let note = parse_file_note("src/main.ts:60,1585861200:60,r").unwrap();
+    /// assert_eq!(note.app(), None);
+    /// ```
+    pub fn app(&self) -> Option<&str> {
+        self.source_file
+            .strip_prefix(".gtm/")?
+            .strip_suffix(".app")
+            .filter(|app| !app.is_empty() && !app.contains('/'))
+    }
+
+    /// Writes this file entry with `source_file` as its path.
+    fn write(&self, f: &mut fmt::Formatter<'_>, source_file: &str) -> fmt::Result {
+        write!(f, "{}:{}", source_file, self.time_spent)?;
+        for (epoch, seconds) in &self.timeline {
+            write!(f, ",{}:{}", epoch, seconds)?;
+        }
+        write!(f, ",{}", self.status)
+    }
+}
+
+impl<'a> CommitNote<'a> {
+    /// Adds the time spent in `other` to this note.
+    ///
+    /// Files present in both notes add up their time spent and timelines,
+    /// and keep the status of this note unless it is `FileStatus::Read`.
+    /// The merged note takes the latest version of both.
+    ///
+    /// ```
+    /// #[macro_use] extern crate maplit;
+    /// use gtm::{*, parse::*};
+    /// let mut note = parse_commit_note("[ver:1,total:90]
+    /// src/main.ts:60,1585861200:60,m
+    /// src/lib.ts:30,1585861200:30,r").unwrap();
+    /// note.merge(parse_commit_note("[ver:1,total:80]
+    /// src/main.ts:40,1585864800:40,r
+    /// docs/a,b.md:40,1585864800:40,r").unwrap());
+    ///
+    /// assert_eq!(note.total, 170);
+    /// assert_eq!(note.files.len(), 3);
+    /// assert_eq!(note.files[0].time_spent, 100);
+    /// assert_eq!(note.files[0].timeline, btreemap! { 1585861200 => 60, 1585864800 => 40 });
+    /// assert_eq!(note.files[0].status, FileStatus::Modified);
+    ///
+    /// note.merge(CommitNote::new(ESCAPED_PATHS_VERSION, 0));
+    /// assert_eq!(note.version, ESCAPED_PATHS_VERSION);
+    /// assert_eq!(note.files[2].source_file, "docs/a,b.md");
+    /// assert!(note.to_string().ends_with("\ndocs/a%2Cb.md:40,1585864800:40,r"));
+    /// ```
+    pub fn merge(&mut self, other: CommitNote<'a>) {
+        let version = self.version.max(other.version);
+        for file in other.files {
+            match self
+                .files
+                .iter_mut()
+                .find(|f| f.source_file == file.source_file && f.raw_path == file.raw_path)
+            {
+                None => self.files.push(file),
+                Some(existing) => {
+                    existing.time_spent += file.time_spent;
+                    for (epoch, seconds) in file.timeline {
+                        *existing.timeline.entry(epoch).or_insert(0) += seconds;
+                    }
+                    if existing.status == FileStatus::Read {
+                        existing.status = file.status;
+                    }
+                }
+            }
+        }
+        self.version = version;
+        self.total += other.total;
+    }
+}
+
 impl CommitNote<'_> {
     pub fn new<'a>(version: u32, total: seconds) -> CommitNote<'a> {
         CommitNote {
@@ -94,28 +303,413 @@ impl CommitNote<'_> {
             files: Vec::new(),
         }
     }
+
+    /// Returns the time spent in applications, e.g., the terminal,
+    /// as opposed to `file_time`.
+    ///
+    /// ```
+    /// use gtm::parse::*;
+    /// let mut note = parse_commit_note("[ver:2,total:100]
+    /// src/main.ts:60,1585861200:60,m
+    /// .gtm/terminal.app:40,1585861200:40,r").unwrap();
+    /// assert_eq!(note.app_time(), 40);
+    /// assert_eq!(note.file_time(), 60);
+    ///
+    /// note.exclude_apps();
+    /// assert_eq!(note.total, 60);
+    /// assert_eq!(note.files.len(), 1);
+    /// assert_eq!(note.app_time(), 0);
+    /// ```
+    pub fn app_time(&self) -> seconds {
+        self.files
+            .iter()
+            .filter(|file| file.app().is_some())
+            .map(|file| file.time_spent)
+            .sum()
+    }
+
+    /// Returns the time spent in files, *i.e.*, excluding `app_time`.
+    pub fn file_time(&self) -> seconds {
+        self.files
+            .iter()
+            .filter(|file| file.app().is_none())
+            .map(|file| file.time_spent)
+            .sum()
+    }
+
+    /// Keeps only the timeline entries of this note whose epoch is within `[from, to]`,
+    /// either of them open when `None`.
+    ///
+    /// The time spent of each file, and the note total,
+    /// are recomputed from the timeline entries kept.
+    /// Files left without timeline entries are removed.
+    ///
+    /// Timeline entries are kept or removed whole, by their epoch,
+    /// *i.e.*, the start of the hour they record.
+    /// When `from` or `to` are not at the start of an hour,
+    /// e.g., days in a time zone at +05:30,
+    /// the hour straddling them counts entirely on the side where it starts.
+    ///
+    /// ```
+    /// #[macro_use] extern crate maplit;
+    /// use gtm::parse::*;
+    /// let mut note = parse_commit_note("[ver:2,total:100]
+    /// src/main.ts:60,1585861200:20,1585864800:40,m
+    /// src/lib.ts:40,1585861200:40,r").unwrap();
+    /// note.trim_timeline(Some(1585864800), None);
+    /// assert_eq!(note.total, 40);
+    /// assert_eq!(note.files.len(), 1);
+    /// assert_eq!(note.files[0].time_spent, 40);
+    /// assert_eq!(note.files[0].timeline, btreemap! { 1585864800 => 40 });
+    /// ```
+    pub fn trim_timeline(&mut self, from: Option<epoch>, to: Option<epoch>) {
+        let within = |epoch: &epoch| {
+            from.map_or(true, |from| *epoch >= from) && to.map_or(true, |to| *epoch <= to)
+        };
+        for file in &mut self.files {
+            file.timeline.retain(|epoch, _| within(epoch));
+            file.time_spent = file.timeline.values().sum();
+        }
+        self.files.retain(|file| !file.timeline.is_empty());
+        self.total = self.files.iter().map(|file| file.time_spent).sum();
+    }
+
+    /// Removes the applications from this note,
+    /// and their time spent from `total`.
+    pub fn exclude_apps(&mut self) {
+        let app_time = self.app_time();
+        self.files.retain(|file| file.app().is_none());
+        self.total = self.total.saturating_sub(app_time);
+    }
+
+    /// Returns a `CommitNote` that owns all its files.
+    /// Owned notes can be stored in caches or sent to other threads.
+    ///
+    /// ```
+    /// use gtm::{*, parse::*};
+    /// let text = String::from("[ver:1,total:60]\nsrc/main.ts:60,1585861200:60,r");
+    /// let note = parse_commit_note(&text).unwrap().into_owned();
+    /// drop(text);
+    /// let handle = std::thread::spawn(move || note.total);
+    /// assert_eq!(handle.join().unwrap(), 60);
+    /// ```
+    pub fn into_owned(self) -> CommitNote<'static> {
+        CommitNote {
+            version: self.version,
+            total: self.total,
+            files: self.files.into_iter().map(FileNote::into_owned).collect(),
+        }
+    }
+}
+
+/// Writes a `FileNote` in the same line format accepted by `parse::parse_file_note`,
+/// *i.e.*, `path:time_spent,epoch:seconds,...,status`.
+///
+/// ```
+/// #[macro_use] extern crate maplit;
+/// use gtm::*;
+///
+/// assert_eq!(FileNote {
+///         source_file: "src/main.ts".into(),
+///         time_spent: 150,
+///         timeline: btreemap! { 1585861200 => 60, 1585875600 => 90 },
+///         status: FileStatus::Read,
+///         raw_path: None,
+///     }.to_string(),
+///     "src/main.ts:150,1585861200:60,1585875600:90,r"
+/// );
+/// ```
+///
+/// The `source_file` is written as it is.
+/// `CommitNote` escapes it when the note version is
+/// `parse::ESCAPED_PATHS_VERSION` or later.
+///
+/// Note that a `FileNote` with an empty timeline is written anyway,
+/// but it cannot be parsed back.
+impl fmt::Display for FileNote<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, &self.source_file)
+    }
+}
+
+/// Writes a `CommitNote` in the note text format stored under `GTM_REFS`.
+/// The header goes in the first line, followed by one line per file.
+/// No trailing newline is written,
+/// so that parsing and writing a note gives back the original text.
+///
+/// ```
+/// use gtm::parse::*;
+///
+/// let message = "[ver:2,total:213]
+/// closebrackets/src/closebrackets.ts:950,1585918800:510,1585922400:400,1585929600:40,r
+/// text/src/char.ts:90,1585918800:90,r";
+/// assert_eq!(parse_commit_note(message).unwrap().to_string(), message);
+/// ```
+///
+/// Notes without files are written as a single header line.
+///
+/// ```
+/// use gtm::*;
+/// assert_eq!(CommitNote::new(1, 0).to_string(), "[ver:1,total:0]");
+/// ```
+impl fmt::Display for CommitNote<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[ver:{},total:{}]", self.version, self.total)?;
+        for file in &self.files {
+            f.write_str("\n")?;
+            if self.version >= parse::ESCAPED_PATHS_VERSION {
+                file.write(f, &parse::escape_path(file.path_bytes()))?;
+            } else {
+                file.write(f, &file.source_file)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Selects whether the time spent in applications is reported,
+/// see `FileNote::app`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AppTime {
+    /// Reports the time spent in applications together with the time spent in files.
+    Include,
+
+    /// Leaves the time spent in applications out, see `CommitNote::exclude_apps`.
+    Exclude,
+}
+
+impl AppTime {
+    /// Leaves the time spent in applications out of `note` when `AppTime::Exclude`.
+    ///
+    /// ```
+    /// use gtm::{*, parse::*};
+    /// let mut note = parse_commit_note("[ver:2,total:100]
+    /// src/main.ts:60,1585861200:60,m
+    /// .gtm/terminal.app:40,1585861200:40,r").unwrap();
+    /// AppTime::Include.apply(&mut note);
+    /// assert_eq!(note.total, 100);
+    /// AppTime::Exclude.apply(&mut note);
+    /// assert_eq!(note.total, 60);
+    /// ```
+    pub fn apply(self, note: &mut CommitNote) {
+        if self == AppTime::Exclude {
+            note.exclude_apps();
+        }
+    }
 }
 
 #[derive(PartialEq, Debug, Serialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct WorkdirStatus<'a> {
     pub total: seconds,
+    /// The time spent in applications, e.g., the terminal.
+    pub app_time: seconds,
+    /// The time spent in files.
+    pub file_time: seconds,
+    /// A summary of `head`, e.g., `feature/x: Add parser [ahead 2, behind 1]`.
     pub label: String,
+    #[serde(flatten)]
+    pub head: HeadStatus,
     pub commit_note: CommitNote<'a>,
+    /// The problems found while computing the status,
+    /// e.g., event files skipped because they cannot be read.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+/// The commit checked out in a working directory.
+#[derive(PartialEq, Debug, Default, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct HeadStatus {
+    /// The checked-out branch, or `None` when HEAD is detached.
+    pub branch: Option<String>,
+    /// The short SHA of HEAD, or `None` when the branch has no commits yet.
+    pub short_id: Option<String>,
+    /// The subject of the HEAD commit.
+    pub subject: Option<String>,
+    /// The number of commits in the branch that are not in its upstream,
+    /// or `None` when the branch has no upstream.
+    pub ahead: Option<usize>,
+    /// The number of commits in the upstream that are not in the branch,
+    /// or `None` when the branch has no upstream.
+    pub behind: Option<usize>,
+}
+
+impl fmt::Display for HeadStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.branch, &self.short_id) {
+            (Some(branch), _) => write!(f, "{}", branch)?,
+            (None, Some(short_id)) => write!(f, "{} (detached)", short_id)?,
+            (None, None) => write!(f, "(no branch)")?,
+        }
+        if let Some(subject) = &self.subject {
+            write!(f, ": {}", subject)?;
+        }
+        match (self.ahead.unwrap_or(0), self.behind.unwrap_or(0)) {
+            (0, 0) => Ok(()),
+            (ahead, 0) => write!(f, " [ahead {}]", ahead),
+            (0, behind) => write!(f, " [behind {}]", behind),
+            (ahead, behind) => write!(f, " [ahead {}, behind {}]", ahead, behind),
+        }
+    }
 }
 
 #[derive(PartialEq, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct Commit<'a> {
     pub author: String,
+    #[serde(default)]
+    pub author_email: String,
+    #[serde(default)]
+    pub committer: String,
+    #[serde(default)]
+    pub committer_email: String,
+    /// The author as `Name <email>`, resolved through the `.mailmap` of the repository,
+    /// so that the commits of a person can be grouped together.
+    /// Set only by `Commit::with_mailmap`, empty otherwise.
+    #[serde(default)]
+    pub identity: String,
     pub date: String,
     pub when: String,
     pub hash: String,
     pub subject: String,
     pub message: String,
     pub project: String,
-    #[serde(borrow)]
     pub note: CommitNote<'a>,
+    /// The place of the commit in the history, only when requested by `NotesFilter::graph`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub graph: Option<CommitGraph>,
+}
+
+/// The place of a commit in the history of its repository,
+/// e.g., to group commits by pull request or release.
+#[derive(PartialEq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct CommitGraph {
+    /// The hashes of the parent commits, the first parent first.
+    pub parents: Vec<String>,
+    /// Whether the commit has more than one parent.
+    pub merge: bool,
+    /// The local branches that point at or contain the commit.
+    pub branches: Vec<String>,
+    /// The tags that point at or contain the commit.
+    pub tags: Vec<String>,
+    /// The number of first-parent steps from the tip of the base branch to the commit,
+    /// or `None` without base branch or when the commit is not in its first-parent history.
+    pub base_distance: Option<usize>,
+}
+
+/// The branches and tags of a repository,
+/// and the first-parent history of the base branch,
+/// read once to compute the `CommitGraph` of many commits.
+struct Decorations {
+    /// The names of the branches, sorted, followed by the names of the tags, sorted.
+    names: Vec<String>,
+    /// The number of branches at the start of `names`.
+    branches: usize,
+    /// The branches and tags containing each commit with a note,
+    /// as a bitset of their indices in `names`.
+    refs: HashMap<Oid, Vec<u64>>,
+    base: HashMap<Oid, usize>,
+}
+
+impl Decorations {
+    /// Reads the branches and tags of `repo` with a single walk of its history.
+    ///
+    /// Commits are walked in topological order, children before parents,
+    /// so that the refs containing a commit are known when it is reached,
+    /// and are then handed down to its parents.
+    /// Only the refs of the commits with a note are kept.
+    fn new(repo: &Repository, base: Option<&str>) -> Result<Self, git2::Error> {
+        let mut branches = Vec::new();
+        let mut tags = Vec::new();
+        for reference in repo.references()? {
+            let reference = reference?;
+            let (name, target) = match (reference.shorthand(), reference.peel_to_commit()) {
+                (Some(name), Ok(target)) => (name.to_owned(), target.id()),
+                _ => continue,
+            };
+            if reference.is_branch() {
+                branches.push((name, target));
+            } else if reference.is_tag() {
+                tags.push((name, target));
+            }
+        }
+        branches.sort();
+        tags.sort();
+
+        let mut decorations = Decorations {
+            names: Vec::with_capacity(branches.len() + tags.len()),
+            branches: branches.len(),
+            refs: HashMap::new(),
+            base: HashMap::new(),
+        };
+        let words = (branches.len() + tags.len() + 63) / 64;
+        let mut tips: HashMap<Oid, Vec<usize>> = HashMap::new();
+        let mut revwalk = repo.revwalk()?;
+        revwalk.set_sorting(Sort::TOPOLOGICAL)?;
+        for (index, (name, target)) in branches.into_iter().chain(tags).enumerate() {
+            decorations.names.push(name);
+            tips.entry(target).or_default().push(index);
+            revwalk.push(target)?;
+        }
+
+        let annotated = repo
+            .notes(Some(GTM_REFS))?
+            .map(|note| note.map(|(_note_id, oid)| oid))
+            .collect::<Result<HashSet<_>, _>>()?;
+        let mut pending: HashMap<Oid, Vec<u64>> = HashMap::new();
+        for oid in revwalk {
+            let oid = oid?;
+            let mut contained = pending.remove(&oid).unwrap_or_else(|| vec![0; words]);
+            for index in tips.get(&oid).into_iter().flatten() {
+                contained[index / 64] |= 1u64 << (index % 64);
+            }
+            for parent in repo.find_commit(oid)?.parent_ids() {
+                let inherited = pending.entry(parent).or_insert_with(|| vec![0; words]);
+                for (word, bits) in inherited.iter_mut().zip(&contained) {
+                    *word |= bits;
+                }
+            }
+            if annotated.contains(&oid) {
+                decorations.refs.insert(oid, contained);
+            }
+        }
+
+        if let Some(base) = base {
+            let mut revwalk = repo.revwalk()?;
+            revwalk.simplify_first_parent()?;
+            revwalk.push(repo.revparse_single(base)?.peel_to_commit()?.id())?;
+            for (distance, oid) in revwalk.enumerate() {
+                decorations.base.insert(oid?, distance);
+            }
+        }
+        Ok(decorations)
+    }
+
+    fn graph(&self, commit: &git2::Commit) -> CommitGraph {
+        let mut branches = Vec::new();
+        let mut tags = Vec::new();
+        if let Some(contained) = self.refs.get(&commit.id()) {
+            for (index, name) in self.names.iter().enumerate() {
+                if contained[index / 64] & (1u64 << (index % 64)) == 0 {
+                    continue;
+                }
+                if index < self.branches {
+                    branches.push(name.clone());
+                } else {
+                    tags.push(name.clone());
+                }
+            }
+        }
+        CommitGraph {
+            parents: commit.parent_ids().map(|oid| oid.to_string()).collect(),
+            merge: commit.parent_count() > 1,
+            branches,
+            tags,
+            base_distance: self.base.get(&commit.id()).copied(),
+        }
+    }
 }
 
 /// Formats a git2 date time in RFC 822 format.
@@ -140,15 +734,31 @@ pub fn format_time(time: git2::Time) -> String {
         .to_string()
 }
 
+fn text(text: Option<&str>) -> String {
+    text.unwrap_or("<invalid utf-8>").to_string()
+}
+
+/// Formats a git2 signature as `Name <email>`.
+fn identity(sig: &git2::Signature) -> String {
+    format!("{} <{}>", text(sig.name()), text(sig.email()))
+}
+
 impl Commit<'_> {
+    /// Creates a `Commit` from a git2 commit and its note,
+    /// without an `identity`, see `Commit::with_mailmap`.
     pub fn new<'a>(commit: &git2::Commit, project: String, note: CommitNote<'a>) -> Commit<'a> {
-        let text = |msg: Option<&str>| msg.unwrap_or("<invalid utf-8>").to_string();
         let mut msg = commit
             .message()
             .unwrap_or("<invalid utf-8>")
             .splitn(2, "\n\n");
+        let author = commit.author();
+        let committer = commit.committer();
         Commit {
-            author: text(commit.author().name()),
+            author: text(author.name()),
+            author_email: text(author.email()),
+            committer: text(committer.name()),
+            committer_email: text(committer.email()),
+            identity: String::new(),
             date: format_time(commit.time()),
             when: format_time(commit.author().when()),
             hash: commit.id().to_string(),
@@ -156,6 +766,43 @@ impl Commit<'_> {
             message: msg.next().unwrap_or("").to_owned(),
             project,
             note,
+            graph: None,
+        }
+    }
+
+    /// Creates a `Commit` like `new`,
+    /// but with the `identity` of the author resolved through `mailmap`,
+    /// usually the mailmap of the repository, see `git2::Repository::mailmap`.
+    pub fn with_mailmap<'a>(
+        commit: &git2::Commit,
+        project: String,
+        note: CommitNote<'a>,
+        mailmap: &Mailmap,
+    ) -> Commit<'a> {
+        let mut result = Commit::new(commit, project, note);
+        result.identity = match commit.author_with_mailmap(mailmap) {
+            Ok(author) => identity(&author),
+            Err(_) => identity(&commit.author()),
+        };
+        result
+    }
+
+    /// Returns a `Commit` that owns its `CommitNote`.
+    pub fn into_owned(self) -> Commit<'static> {
+        Commit {
+            author: self.author,
+            author_email: self.author_email,
+            committer: self.committer,
+            committer_email: self.committer_email,
+            identity: self.identity,
+            date: self.date,
+            when: self.when,
+            hash: self.hash,
+            subject: self.subject,
+            message: self.message,
+            project: self.project,
+            note: self.note.into_owned(),
+            graph: self.graph,
         }
     }
 }
@@ -166,10 +813,131 @@ pub fn get_commits(_path: &str) -> Result<(), git2::Error> {
     Ok(())
 }
 
+/// The time zone in which the days given to a `NotesFilter` start and end.
+///
+/// Parsed from `local`, `utc`, or the name of an IANA time zone, ignoring case.
+/// Defaults to UTC.
+///
+/// ```
+/// use gtm::DateZone;
+/// assert_eq!("local".parse(), Ok(DateZone::Local));
+/// assert_eq!("UTC".parse(), Ok(DateZone::Utc));
+/// assert_eq!("Europe/Madrid".parse(), Ok(DateZone::Named(chrono_tz::Europe::Madrid)));
+/// assert_eq!("europe/madrid".parse(), Ok(DateZone::Named(chrono_tz::Europe::Madrid)));
+/// assert!("Mars/Olympus_Mons".parse::<DateZone>().is_err());
+/// ```
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum DateZone {
+    /// The time zone of this machine.
+    Local,
+    Utc,
+    Named(Tz),
+}
+
+impl Default for DateZone {
+    fn default() -> Self {
+        DateZone::Utc
+    }
+}
+
+impl FromStr for DateZone {
+    type Err = String;
+
+    fn from_str(zone: &str) -> Result<Self, Self::Err> {
+        match zone.to_lowercase().as_str() {
+            "local" => Ok(DateZone::Local),
+            "utc" => Ok(DateZone::Utc),
+            _ => zone
+                .parse()
+                .or_else(|err| {
+                    TZ_VARIANTS
+                        .iter()
+                        .copied()
+                        .find(|tz| tz.name().eq_ignore_ascii_case(zone))
+                        .ok_or(err)
+                })
+                .map(DateZone::Named),
+        }
+    }
+}
+
+impl DateZone {
+    /// Returns the Unix epoch at which `date` starts in this time zone.
+    ///
+    /// A day usually starts at midnight,
+    /// or at the first hour after midnight when a daylight saving time transition skips it.
+    ///
+    /// ```
+    /// use chrono::NaiveDate;
+    /// use gtm::DateZone;
+    /// let date = NaiveDate::from_ymd(2020, 4, 3);
+    /// assert_eq!(DateZone::Utc.start_of_day(date), 1585872000);
+    /// let madrid = DateZone::Named(chrono_tz::Europe::Madrid);
+    /// assert_eq!(madrid.start_of_day(date), 1585872000 - 2 * 3600);
+    /// // Midnight is skipped in Sao Paulo on 2018-11-04
+    /// let sao_paulo = DateZone::Named(chrono_tz::America::Sao_Paulo);
+    /// assert_eq!(sao_paulo.start_of_day(NaiveDate::from_ymd(2018, 11, 4)), 1541300400);
+    /// ```
+    pub fn start_of_day(&self, date: NaiveDate) -> epoch {
+        fn start<T: TimeZone>(zone: &T, date: NaiveDate) -> epoch {
+            (0..24)
+                .find_map(|hour| {
+                    zone.from_local_datetime(&date.and_hms(hour, 0, 0))
+                        .earliest()
+                })
+                .expect("A day has at least one valid hour")
+                .timestamp()
+        }
+        match self {
+            DateZone::Local => start(&Local, date),
+            DateZone::Utc => start(&Utc, date),
+            DateZone::Named(tz) => start(tz, date),
+        }
+    }
+
+    /// Returns the last Unix epoch of `date` in this time zone,
+    /// *i.e.*, one second before the start of the next day.
+    ///
+    /// ```
+    /// use chrono::NaiveDate;
+    /// use gtm::DateZone;
+    /// let date = NaiveDate::from_ymd(2020, 4, 3);
+    /// assert_eq!(DateZone::Utc.end_of_day(date), 1585872000 + 86400 - 1);
+    /// ```
+    pub fn end_of_day(&self, date: NaiveDate) -> epoch {
+        self.start_of_day(date + Duration::days(1)) - 1
+    }
+}
+
 pub struct NotesFilter {
+    /// The first Unix epoch, inclusive, of the commits accepted.
     pub from: Option<epoch>,
+    /// The last Unix epoch, inclusive, of the commits accepted.
     pub to: Option<epoch>,
+    /// Whether `from` and `to` apply to the timeline entries of the notes,
+    /// instead of to the commit time.
+    pub by_activity: bool,
     pub needle: Option<String>,
+    pub parse_mode: ParseMode,
+    pub versions: NoteVersions,
+    /// Case-insensitive text contained in the author name or email.
+    pub author: Option<String>,
+    /// Git pathspecs, e.g., `src/**`, of which notes must contain at least one file.
+    pub paths: Vec<String>,
+    /// Whether to remove the files not matching `paths` from the notes.
+    pub trim_paths: bool,
+    /// A revision, e.g., `feature`, or a range, e.g., `main..feature`,
+    /// that commits must be reachable from.
+    pub revisions: Option<String>,
+    /// Whether the time spent in applications is reported, see `AppTime`.
+    pub app_time: AppTime,
+    /// The minimum `CommitNote::total` of notes,
+    /// after excluding applications and trimming timelines and paths.
+    pub min_total: Option<seconds>,
+    /// Whether to compute the `CommitGraph` of each commit.
+    pub graph: bool,
+    /// The branch from which `CommitGraph::base_distance` is computed.
+    pub base: Option<String>,
 }
 
 impl NotesFilter {
@@ -178,10 +946,36 @@ impl NotesFilter {
         Self {
             from: None,
             to: None,
+            by_activity: false,
             needle: None,
+            parse_mode: ParseMode::Strict,
+            versions: NoteVersions::default(),
+            author: None,
+            paths: Vec::new(),
+            trim_paths: false,
+            revisions: None,
+            app_time: AppTime::Include,
+            min_total: None,
+            graph: false,
+            base: None,
         }
     }
 
+    /// Accepts only commits from the start of the day `from`,
+    /// until the end of the day `to`, both inclusive,
+    /// where days start and end in `zone`.
+    /// Either day may be left open.
+    pub fn days(
+        &mut self,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+        zone: DateZone,
+    ) -> &mut Self {
+        self.from = from.map(|from| zone.start_of_day(from));
+        self.to = to.map(|to| zone.end_of_day(to));
+        self
+    }
+
     pub fn from<T: TimeZone>(&mut self, date: DateTime<T>) -> &mut Self {
         self.from = Some(date.timestamp());
         self
@@ -197,10 +991,95 @@ impl NotesFilter {
         self
     }
 
-    fn filter(&self, commit: &git2::Commit) -> bool {
-        let time = commit.time().seconds() + commit.time().offset_minutes() as i64 * 60;
-        self.from.map_or(true, |from| time >= from)
-            && self.to.map_or(true, |to| time <= to)
+    /// Recovers the valid files of partially corrupt notes,
+    /// instead of reporting the whole note as a `NoteError`.
+    pub fn lenient(&mut self) -> &mut Self {
+        self.parse_mode = ParseMode::Lenient;
+        self
+    }
+
+    /// Parses notes with the decoders registered in `versions`,
+    /// instead of only the versions known to this crate.
+    pub fn versions(&mut self, versions: NoteVersions) -> &mut Self {
+        self.versions = versions;
+        self
+    }
+
+    /// Applies `from` and `to` to the time spent, instead of to the commit time,
+    /// so that a note counts the time spent within the dates whenever it was committed.
+    ///
+    /// Notes are trimmed to the timeline entries within `from` and `to`,
+    /// see `CommitNote::trim_timeline`,
+    /// and notes without time spent within them are skipped.
+    /// Notes are left untouched when neither `from` nor `to` are set.
+    pub fn by_activity(&mut self) -> &mut Self {
+        self.by_activity = true;
+        self
+    }
+
+    /// Accepts only commits whose author name or email contains `author`, ignoring case,
+    /// either as written in the commit or as resolved through the `.mailmap` of the repository.
+    pub fn author(&mut self, author: String) -> &mut Self {
+        self.author = Some(author);
+        self
+    }
+
+    /// Accepts only notes with at least one file matching any of the git pathspecs in `paths`,
+    /// e.g., `src/**`.
+    /// When `trim` is set, the files not matching are removed from the notes,
+    /// and their time spent from the note total.
+    pub fn paths(&mut self, paths: Vec<String>, trim: bool) -> &mut Self {
+        self.paths = paths;
+        self.trim_paths = trim;
+        self
+    }
+
+    /// Accepts only commits reachable from `revisions`,
+    /// either a single revision, e.g., `feature`,
+    /// or a range as understood by `git log`, e.g., `main..feature` or `main...feature`.
+    pub fn revisions(&mut self, revisions: String) -> &mut Self {
+        self.revisions = Some(revisions);
+        self
+    }
+
+    /// Removes the applications from the notes, see `CommitNote::exclude_apps`,
+    /// before any other note filter is applied.
+    /// Notes with time spent only in applications are skipped.
+    pub fn exclude_apps(&mut self) -> &mut Self {
+        self.app_time = AppTime::Exclude;
+        self
+    }
+
+    /// Accepts only notes whose total is at least `min_total` seconds.
+    pub fn min_total(&mut self, min_total: seconds) -> &mut Self {
+        self.min_total = Some(min_total);
+        self
+    }
+
+    /// Computes the `CommitGraph` of each commit,
+    /// with the first-parent distance from the `base` branch, if any.
+    ///
+    /// Finding the branches and tags that contain each commit walks the history,
+    /// and therefore it is not done by default.
+    pub fn graph(&mut self, base: Option<String>) -> &mut Self {
+        self.graph = true;
+        self.base = base;
+        self
+    }
+
+    fn filter(&self, commit: &git2::Commit, mailmap: &Mailmap) -> bool {
+        let time = commit.time().seconds();
+        let dated = |time: epoch| {
+            self.by_activity
+                || (self.from.map_or(true, |from| time >= from)
+                    && self.to.map_or(true, |to| time <= to))
+        };
+        let contains = |text: Option<&str>, needle: &str| {
+            text.map_or(false, |text| {
+                text.to_lowercase().contains(needle.to_lowercase().as_str())
+            })
+        };
+        dated(time)
             && self.needle.as_ref().map_or(true, |msg: &String| {
                 if let Some(message) = commit.message() {
                     message.to_lowercase().contains(msg.to_lowercase().as_str())
@@ -208,43 +1087,291 @@ impl NotesFilter {
                     true
                 }
             })
+            && self.author.as_ref().map_or(true, |author| {
+                let matches = |sig: &git2::Signature| {
+                    contains(sig.name(), author) || contains(sig.email(), author)
+                };
+                matches(&commit.author())
+                    || commit
+                        .author_with_mailmap(mailmap)
+                        .map_or(false, |sig| matches(&sig))
+            })
+    }
+
+    /// Returns the notes left by the note filters,
+    /// after removing applications, trimming timelines and trimming paths, as requested.
+    fn filter_note<'a>(
+        &self,
+        pathspec: Option<&Pathspec>,
+        mut note: CommitNote<'a>,
+    ) -> Option<CommitNote<'a>> {
+        if self.app_time == AppTime::Exclude {
+            note.exclude_apps();
+            if note.files.is_empty() {
+                return None;
+            }
+        }
+        if self.by_activity && (self.from.is_some() || self.to.is_some()) {
+            note.trim_timeline(self.from, self.to);
+            if note.files.is_empty() {
+                return None;
+            }
+        }
+        if let Some(pathspec) = pathspec {
+            let matches = |file: &FileNote| {
+                pathspec.matches_path(Path::new(&*file.source_file), PathspecFlags::DEFAULT)
+            };
+            if !note.files.iter().any(matches) {
+                return None;
+            }
+            if self.trim_paths {
+                let trimmed: seconds = note
+                    .files
+                    .iter()
+                    .filter(|file| !matches(file))
+                    .map(|file| file.time_spent)
+                    .sum();
+                note.files.retain(matches);
+                note.total = note.total.saturating_sub(trimmed);
+            }
+        }
+        match self.min_total {
+            Some(min_total) if note.total < min_total => None,
+            _ => Some(note),
+        }
+    }
+
+    /// Returns a walk over the commits reachable from `revisions`, if any.
+    ///
+    /// A symmetric range `A...B` walks the commits reachable from either side
+    /// but not from both, as `git log` does.
+    fn revwalk<'r>(&self, repo: &'r Repository) -> Result<Option<Revwalk<'r>>, git2::Error> {
+        let revisions = match &self.revisions {
+            None => return Ok(None),
+            Some(revisions) => revisions,
+        };
+        let mut revwalk = repo.revwalk()?;
+        revwalk.set_sorting(Sort::NONE)?;
+        if let Some(index) = revisions.find("...") {
+            let resolve = |rev: &str| {
+                let rev = if rev.is_empty() { "HEAD" } else { rev };
+                repo.revparse_single(rev)?
+                    .peel_to_commit()
+                    .map(|commit| commit.id())
+            };
+            let left = resolve(&revisions[..index])?;
+            let right = resolve(&revisions[index + 3..])?;
+            revwalk.push(left)?;
+            revwalk.push(right)?;
+            match repo.merge_bases(left, right) {
+                Ok(bases) => {
+                    for base in bases.iter() {
+                        revwalk.hide(*base)?;
+                    }
+                }
+                Err(err) if err.code() == ErrorCode::NotFound => {}
+                Err(err) => return Err(err),
+            }
+        } else if revisions.contains("..") {
+            revwalk.push_range(revisions)?;
+        } else {
+            revwalk.push(repo.revparse_single(revisions)?.peel_to_commit()?.id())?;
+        }
+        Ok(Some(revwalk))
+    }
+}
+
+/// Represents the errors found while iterating the notes of a repository.
+#[derive(Debug)]
+pub enum NoteError {
+    /// Occurs when git cannot read the notes reference or a commit.
+    Git(git2::Error),
+
+    /// Occurs when the commit `oid` with a note does not exist,
+    /// *e.g.*, after a rebase followed by `git gc`.
+    MissingCommit { oid: Oid },
+
+    /// Occurs when the note attached to `oid` is not valid UTF-8.
+    InvalidUtf8 { oid: Oid },
+
+    /// Occurs when the note attached to `oid` is not a valid `CommitNote`.
+    Parse { oid: Oid, err: CommitNoteParseError },
+}
+
+impl NoteError {
+    /// Returns the commit whose note could not be read, if any.
+    pub fn oid(&self) -> Option<Oid> {
+        match self {
+            NoteError::Git(_) => None,
+            NoteError::MissingCommit { oid }
+            | NoteError::InvalidUtf8 { oid }
+            | NoteError::Parse { oid, .. } => Some(*oid),
+        }
+    }
+}
+
+impl fmt::Display for NoteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NoteError::Git(err) => write!(f, "Git2 error: {}", err),
+            NoteError::MissingCommit { oid } => write!(f, "Commit {} with a note not found", oid),
+            NoteError::InvalidUtf8 { oid } => {
+                write!(f, "Note for commit {} is not valid UTF-8", oid)
+            }
+            NoteError::Parse { oid, err } if f.alternate() => {
+                write!(f, "Could not parse note for commit {}: {:#}", oid, err)
+            }
+            NoteError::Parse { oid, err } => {
+                write!(f, "Could not parse note for commit {}: {}", oid, err)
+            }
+        }
+    }
+}
+
+impl std::error::Error for NoteError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            NoteError::Git(err) => Some(err),
+            NoteError::Parse { err, .. } => Some(err),
+            NoteError::MissingCommit { .. } | NoteError::InvalidUtf8 { .. } => None,
+        }
+    }
+}
+
+impl From<git2::Error> for NoteError {
+    fn from(err: git2::Error) -> Self {
+        NoteError::Git(err)
     }
 }
 
+/// A commit together with the gtm note attached to it.
+///
+/// The parsed `Commit` owns its note,
+/// so that it can outlive the `git2::Note` it was read from.
+/// In `ParseMode::Lenient`, `diagnostics` holds the lines skipped from the note.
 #[derive(Debug)]
-pub struct GitCommitNote<'a> {
-    pub commit: Commit<'a>,
-    note: git2::Note<'a>,
+pub struct GitCommitNote<'r> {
+    pub commit: Commit<'static>,
+    pub git_commit: git2::Commit<'r>,
+    pub diagnostics: Vec<CommitNoteParseError>,
 }
 
-pub fn get_notes<'r, F>(
-    mut with: F,
+/// Lazy iterator over the gtm notes of a repository, created by `get_notes`.
+///
+/// Commits not accepted by the filter are skipped.
+/// Notes that cannot be read are reported as `NoteError`s,
+/// so that the caller can decide whether to skip them or stop.
+pub struct Notes<'r> {
+    source: NoteSource<'r>,
     repo: &'r Repository,
-    project: &str,
-    filter: &NotesFilter,
-) -> Result<(), git2::Error>
-where
-    F: FnMut(GitCommitNote<'r>) -> (),
-{
-    let notes = repo.notes(Some(GTM_REFS))?;
+    project: String,
+    filter: &'r NotesFilter,
+    pathspec: Option<Pathspec>,
+    mailmap: Mailmap,
+    decorations: Option<Decorations>,
+}
 
-    for note_assoc in notes {
-        let p = note_assoc.unwrap();
-        let commit = repo.find_commit(p.1)?;
-
-        if filter.filter(&commit) {
-            let note = repo.find_note(Some(GTM_REFS), p.1)?;
-            let note_message = unsafe { (*(&note as *const Note)).message() };
-            if let Some(note_message) = note_message {
-                if let Ok(commit_note) = parse_commit_note(note_message) {
-                    with(GitCommitNote {
-                        commit: Commit::new(&commit, project.to_owned(), commit_note),
-                        note,
-                    });
-                }
+/// Yields the commits to read notes from:
+/// every annotated commit, or the commits of a revision walk.
+enum NoteSource<'r> {
+    Notes(git2::Notes<'r>),
+    Revwalk(Revwalk<'r>),
+}
+
+impl<'r> Notes<'r> {
+    fn read(&self, oid: Oid) -> Result<Option<GitCommitNote<'r>>, NoteError> {
+        let commit = match self.repo.find_commit(oid) {
+            Ok(commit) => commit,
+            Err(err) if err.code() == ErrorCode::NotFound => {
+                return Err(NoteError::MissingCommit { oid })
             }
+            Err(err) => return Err(err.into()),
+        };
+        if !self.filter.filter(&commit, &self.mailmap) {
+            return Ok(None);
+        }
+
+        let note = match self.repo.find_note(Some(GTM_REFS), oid) {
+            Ok(note) => note,
+            Err(err) if err.code() == ErrorCode::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let message = note
+            .message()
+            .ok_or_else(|| NoteError::InvalidUtf8 { oid })?;
+        let (note, diagnostics) = self
+            .filter
+            .versions
+            .parse_with(message, self.filter.parse_mode)
+            .map_err(|err| NoteError::Parse { oid, err })?;
+        let note = match self.filter.filter_note(self.pathspec.as_ref(), note) {
+            Some(note) => note.into_owned(),
+            None => return Ok(None),
+        };
+        let mut result = Commit::with_mailmap(&commit, self.project.clone(), note, &self.mailmap);
+        if let Some(decorations) = &self.decorations {
+            result.graph = Some(decorations.graph(&commit));
         }
+        Ok(Some(GitCommitNote {
+            commit: result,
+            git_commit: commit,
+            diagnostics,
+        }))
     }
+}
 
-    Ok(())
+impl<'r> Iterator for Notes<'r> {
+    type Item = Result<GitCommitNote<'r>, NoteError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let oid = match &mut self.source {
+                NoteSource::Notes(notes) => notes.next()?.map(|(_note_id, oid)| oid),
+                NoteSource::Revwalk(revwalk) => revwalk.next()?,
+            };
+            let oid = match oid {
+                Err(err) => return Some(Err(err.into())),
+                Ok(oid) => oid,
+            };
+            if let Some(item) = self.read(oid).transpose() {
+                return Some(item);
+            }
+        }
+    }
+}
+
+/// Returns an iterator over the notes in `repo` accepted by `filter`.
+///
+/// Fails when the repository has no gtm notes,
+/// or when the paths, revisions or base branch of `filter` are not valid.
+pub fn get_notes<'r>(
+    repo: &'r Repository,
+    project: &str,
+    filter: &'r NotesFilter,
+) -> Result<Notes<'r>, git2::Error> {
+    let pathspec = if filter.paths.is_empty() {
+        None
+    } else {
+        Some(Pathspec::new(&filter.paths)?)
+    };
+    let notes = repo.notes(Some(GTM_REFS))?;
+    Ok(Notes {
+        source: match filter.revwalk(repo)? {
+            Some(revwalk) => NoteSource::Revwalk(revwalk),
+            None => NoteSource::Notes(notes),
+        },
+        repo,
+        project: project.to_owned(),
+        filter,
+        pathspec,
+        mailmap: repo.mailmap().unwrap_or_else(|err| {
+            log::warn!("Skipping invalid mailmap: {}", err);
+            Mailmap::new().expect("Could not create an empty mailmap")
+        }),
+        decorations: if filter.graph {
+            Some(Decorations::new(repo, filter.base.as_deref())?)
+        } else {
+            None
+        },
+    })
 }
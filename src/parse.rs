@@ -1,6 +1,5 @@
-use crate::{epoch, seconds, CommitNote, FileNote};
-use regex::Regex;
-use std::{collections::BTreeMap, num::IntErrorKind};
+use crate::{epoch, seconds, CommitNote, FileNote, FileStatus};
+use std::{borrow::Cow, collections::BTreeMap, fmt, num::IntErrorKind, ops::Range};
 
 /// Parses a key-value in "key:value" format.
 ///
@@ -49,7 +48,14 @@ pub enum FileNoteParseError {
     /// ```
     InvalidTimelineFormat,
 
+    /// Occurs when the seconds of a timeline entry are not a valid number.
     ///
+    /// ```
+    /// #![feature(int_error_matching)]
+    /// use std::num::IntErrorKind;
+    /// use gtm::parse::*;
+    /// assert_eq!(parse_file_note("src/file.ts:123,1585861200:35a,m"), Err(FileNoteParseError::InvalidTimespent { kind: IntErrorKind::InvalidDigit }));
+    /// ```
     InvalidTimespent { kind: IntErrorKind },
 
     /// Occurs when the total time spent is not a valid number.
@@ -62,15 +68,57 @@ pub enum FileNoteParseError {
     /// ```
     InvalidTotalTimespent { kind: IntErrorKind },
 
-    /// Occurs when the status of the parsed file note is not recognized.
+    /// Occurs when the status of the parsed file note is not a status at all,
+    /// *i.e.*, it is not made of letters.
+    /// Unknown letters are parsed as `FileStatus::Unknown`.
     ///
     /// ```
-    /// use gtm::parse::*;
-    /// assert_eq!(parse_file_note("src/file.ts:123,1585861200:354,a"), Err(FileNoteParseError::StatusNotRecognized { got: "a".to_owned() }));
+    /// use gtm::{*, parse::*};
+    /// assert_eq!(parse_file_note("src/file.ts:123,1585861200:354,4"), Err(FileNoteParseError::StatusNotRecognized { got: "4".to_owned() }));
+    /// assert_eq!(parse_file_note("src/file.ts:123,1585861200:354,a").unwrap().status, FileStatus::Unknown("a".to_owned()));
     /// ```
     StatusNotRecognized { got: String },
 }
 
+impl fmt::Display for FileNoteParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileNoteParseError::NotEnoughEntries => write!(
+                f,
+                "not enough entries, expected `path:time_spent,epoch:seconds,...,status`"
+            ),
+            FileNoteParseError::UnrecognizedFilepath => {
+                write!(f, "unrecognized file path, expected `path:time_spent`")
+            }
+            FileNoteParseError::InvalidTimelineFormat => {
+                write!(f, "invalid timeline entry, expected `epoch:seconds`")
+            }
+            FileNoteParseError::InvalidTimespent { kind } => {
+                write!(f, "invalid seconds in timeline entry ({:?})", kind)
+            }
+            FileNoteParseError::InvalidTotalTimespent { kind } => {
+                write!(f, "invalid total time spent ({:?})", kind)
+            }
+            FileNoteParseError::StatusNotRecognized { got } => {
+                write!(
+                    f,
+                    "status `{}` not recognized, expected a letter such as `m`, `r` or `d`",
+                    got
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for FileNoteParseError {}
+
+/// Returns the byte range of `part` within `text`.
+/// `part` must be a subslice of `text`.
+fn span_of(text: &str, part: &str) -> Range<usize> {
+    let start = part.as_ptr() as usize - text.as_ptr() as usize;
+    start..start + part.len()
+}
+
 /// Parses a file note entry.
 ///
 /// ```
@@ -80,20 +128,21 @@ pub enum FileNoteParseError {
 /// assert_eq!(
 ///     parse_file_note("src/file.ts:150,1585861200:60,1585875600:90,m").unwrap(),
 ///     FileNote {
-///         source_file: "src/file.ts",
+///         source_file: "src/file.ts".into(),
 ///         time_spent: 150,
 ///         timeline: btreemap! {
 ///             1585861200 => 60,
 ///             1585875600 => 90,
 ///         },
-///         status: "m",
+///         status: FileStatus::Modified,
+///         raw_path: None,
 ///     }
 /// );
 ///
 /// assert_eq!(
 ///  parse_file_note("comment/src/comment.ts:2797,1585861200:354,1585875600:50,1585879200:240,1585908000:444,1585918800:1629,1585929600:80,m").unwrap(),
 ///  FileNote {
-///   source_file: "comment/src/comment.ts",
+///   source_file: "comment/src/comment.ts".into(),
 ///   time_spent: 2797,
 ///   timeline: btreemap! {
 ///     1585861200 => 354,
@@ -103,60 +152,494 @@ pub enum FileNoteParseError {
 ///     1585918800 => 1629,
 ///     1585929600 => 80,
 ///   },
-///   status: "m",
+///   status: FileStatus::Modified,
+///   raw_path: None,
 /// });
 /// ```
+///
+/// File paths containing `,` or `:` are supported as well.
+/// When the entry cannot be parsed splitting from the left,
+/// it is parsed again from the right:
+/// the status and timeline entries are taken from the end of the line,
+/// and the time spent is the number after the last `:` of what remains.
+///
+/// ```
+/// #[macro_use] extern crate maplit;
+/// use gtm::{*, parse::*};
+///
+/// assert_eq!(
+///     parse_file_note("docs/a,b.md:60,1585861200:60,r").unwrap(),
+///     FileNote {
+///         source_file: "docs/a,b.md".into(),
+///         time_spent: 60,
+///         timeline: btreemap! { 1585861200 => 60 },
+///         status: FileStatus::Read,
+///         raw_path: None,
+///     }
+/// );
+/// assert_eq!(
+///     parse_file_note("C:odd:name:60,1585861200:60,r").unwrap().source_file,
+///     "C:odd:name"
+/// );
+/// ```
+///
+/// Paths containing `,<number>:<number>` are not recovered this way,
+/// since they are more likely several entries glued together than an actual path.
+///
+/// ```
+/// use gtm::parse::*;
+/// assert_eq!(
+///     parse_file_note("src/a.ts:60,1585861200:60,rsrc/b.ts:30,1585861200:30,r"),
+///     Err(FileNoteParseError::InvalidTimelineFormat)
+/// );
+/// ```
+///
+/// Note that paths ending in `,<number>:<number>` are still ambiguous in this format.
+/// Notes with version `ESCAPED_PATHS_VERSION` or later escape paths with `escape_path`,
+/// which `parse_commit_note` decodes but this function keeps as they are.
 pub fn parse_file_note<'a>(file_entry: &'a str) -> Result<FileNote<'a>, FileNoteParseError> {
-    let mut parts = file_entry.split(',');
-    let (file_name, time_spent) = parse_key_value(
-        parts
+    let mut builder = NoteBuilder::new();
+    visit_file_note(file_entry, &mut builder).map_err(|(err, _span)| err)?;
+    Ok(builder.files.pop().expect("File note already visited"))
+}
+
+/// Receives the components of a note as they are parsed,
+/// without building any intermediate `FileNote` nor timeline map.
+///
+/// All methods do nothing by default,
+/// so that visitors only implement the events they are interested in.
+/// Each line is validated before any of its events is reported,
+/// so that a valid line is reported once, from `file` to `file_end`,
+/// and an invalid line is reported only to `error`.
+pub trait NoteVisitor<'a> {
+    /// Called once, before any other event, with the header of the note.
+    fn header(&mut self, _version: u32, _total: seconds) {}
+
+    /// Called at the start of each file entry.
+    fn file(&mut self, _source_file: &'a str, _time_spent: seconds) {}
+
+    /// Called for each timeline entry of the current file, in order of appearance.
+    fn timeline(&mut self, _epoch: epoch, _seconds: seconds) {}
+
+    /// Called when the current file entry is complete.
+    /// Use `FileStatus::from` to interpret `status`.
+    fn file_end(&mut self, _status: &'a str) {}
+
+    /// Called for unknown versions and invalid lines.
+    /// Returning `Err` aborts the parse, which is what happens by default.
+    /// Returning `Ok` skips the invalid line and carries on.
+    fn error(&mut self, err: CommitNoteParseError) -> Result<(), CommitNoteParseError> {
+        Err(err)
+    }
+}
+
+/// Visits a note with the decoders of the versions known to this crate.
+///
+/// `parse_commit_note` is built on top of this function.
+/// It is faster when only part of each note is needed,
+/// *e.g.*, to sum the time spent across many notes.
+///
+/// ```
+/// use gtm::parse::*;
+///
+/// #[derive(Default)]
+/// struct Totals {
+///     files: u32,
+///     time_spent: u32,
+/// }
+///
+/// impl NoteVisitor<'_> for Totals {
+///     fn file(&mut self, _source_file: &str, time_spent: u32) {
+///         self.time_spent += time_spent;
+///     }
+///
+///     fn file_end(&mut self, _status: &str) {
+///         self.files += 1;
+///     }
+/// }
+///
+/// let mut totals = Totals::default();
+/// visit_commit_note("[ver:1,total:150]
+/// src/main.ts:90,1585861200:90,r
+/// src/lib.ts:60,1585861200:60,m", &mut totals).unwrap();
+/// assert_eq!(totals.files, 2);
+/// assert_eq!(totals.time_spent, 150);
+/// ```
+pub fn visit_commit_note<'a, V: NoteVisitor<'a>>(
+    message: &'a str,
+    visitor: &mut V,
+) -> Result<(), CommitNoteParseError> {
+    BUILTIN_VERSIONS.visit(message, visitor)
+}
+
+/// Visits a file note entry.
+/// When it fails, it also returns the byte range within `file_entry`
+/// of the component that could not be parsed.
+///
+/// The entry is validated completely before any event is reported to `visitor`,
+/// so that invalid entries report nothing and valid ones are reported once.
+fn visit_file_note<'a>(
+    file_entry: &'a str,
+    visitor: &mut dyn NoteVisitor<'a>,
+) -> Result<(), (FileNoteParseError, Range<usize>)> {
+    let entry = FileEntry::from_left(file_entry)
+        .or_else(|err| FileEntry::from_right(file_entry).ok_or(err))?;
+    entry.report(visitor);
+    Ok(())
+}
+
+/// Parses the status of a file note entry.
+/// Any non-empty sequence of letters is accepted,
+/// so that statuses added by future gtm versions are kept as `FileStatus::Unknown`.
+fn parse_status(status: &str) -> Option<&str> {
+    if !status.is_empty() && status.chars().all(|c| c.is_ascii_alphabetic()) {
+        Some(status)
+    } else {
+        None
+    }
+}
+
+/// Parses a timeline entry in `epoch:seconds` format.
+fn parse_time_entry(text: &str) -> Option<(epoch, seconds)> {
+    let (epoch, seconds) = parse_key_value(text)?;
+    Some((epoch.parse().ok()?, seconds.parse().ok()?))
+}
+
+/// A file note entry already validated,
+/// whose `timeline` is the text of its comma separated timeline entries.
+struct FileEntry<'a> {
+    source_file: &'a str,
+    time_spent: seconds,
+    timeline: &'a str,
+    status: &'a str,
+}
+
+impl<'a> FileEntry<'a> {
+    /// Validates a file note entry splitting on `,` and the first `:`.
+    /// Errors are reported according to this interpretation.
+    fn from_left(file_entry: &'a str) -> Result<Self, (FileNoteParseError, Range<usize>)> {
+        let whole = || 0..file_entry.len();
+        let mut parts = file_entry.split(',');
+        let first = parts
             .next()
-            .ok_or_else(|| FileNoteParseError::NotEnoughEntries)?,
-    )
-    .ok_or_else(|| FileNoteParseError::UnrecognizedFilepath)?;
-
-    let status = match parts
-        .next_back()
-        .ok_or_else(|| FileNoteParseError::NotEnoughEntries)?
-    {
-        s @ "m" | s @ "r" | s @ "d" => s,
-        got => {
-            return Err(FileNoteParseError::StatusNotRecognized {
-                got: got.to_owned(),
-            })
-        }
-    };
+            .ok_or_else(|| (FileNoteParseError::NotEnoughEntries, whole()))?;
+        let (source_file, time_spent) = parse_key_value(first).ok_or_else(|| {
+            (
+                FileNoteParseError::UnrecognizedFilepath,
+                span_of(file_entry, first),
+            )
+        })?;
 
-    let mut timeline = BTreeMap::new();
-    for time_entry in parts {
-        let (epoch, seconds) =
-            parse_key_value(time_entry).ok_or_else(|| FileNoteParseError::InvalidTimelineFormat)?;
-        timeline.insert(
-            epoch
-                .parse::<epoch>()
-                .map_err(|_| FileNoteParseError::InvalidTimelineFormat)?,
-            seconds
-                .parse::<seconds>()
-                .map_err(|err| FileNoteParseError::InvalidTimespent {
+        let last = parts
+            .next_back()
+            .ok_or_else(|| (FileNoteParseError::NotEnoughEntries, whole()))?;
+        let status = parse_status(last).ok_or_else(|| {
+            (
+                FileNoteParseError::StatusNotRecognized {
+                    got: last.to_owned(),
+                },
+                span_of(file_entry, last),
+            )
+        })?;
+
+        let time_spent = time_spent.parse::<seconds>().map_err(|err| {
+            (
+                FileNoteParseError::InvalidTotalTimespent {
                     kind: err.kind().to_owned(),
-                })?,
-        );
+                },
+                span_of(file_entry, time_spent),
+            )
+        })?;
+
+        let mut empty = true;
+        for time_entry in parts {
+            let invalid_format = || {
+                (
+                    FileNoteParseError::InvalidTimelineFormat,
+                    span_of(file_entry, time_entry),
+                )
+            };
+            let (epoch, seconds) = parse_key_value(time_entry).ok_or_else(invalid_format)?;
+            epoch.parse::<epoch>().map_err(|_| invalid_format())?;
+            seconds.parse::<seconds>().map_err(|err| {
+                (
+                    FileNoteParseError::InvalidTimespent {
+                        kind: err.kind().to_owned(),
+                    },
+                    span_of(file_entry, seconds),
+                )
+            })?;
+            empty = false;
+        }
+        if empty {
+            return Err((FileNoteParseError::NotEnoughEntries, whole()));
+        }
+
+        Ok(FileEntry {
+            source_file,
+            time_spent,
+            timeline: &file_entry[first.len() + 1..file_entry.len() - last.len() - 1],
+            status,
+        })
+    }
+
+    /// Validates a file note entry from the right,
+    /// so that file paths can contain `,` and `:`.
+    ///
+    /// Timeline entries are taken from the end while they look like `epoch:seconds`
+    /// and what remains still ends in `:time_spent`.
+    /// The file path left must not contain a `,epoch:seconds` segment,
+    /// which is rather a sign of lines glued together by a lost line break.
+    fn from_right(file_entry: &'a str) -> Option<Self> {
+        let mut parts = file_entry.rsplitn(2, ',');
+        let status = parse_status(parts.next()?)?;
+        let entries = parts.next()?;
+
+        let time_spent_of = |text: &str| -> Option<seconds> {
+            let mut parts = text.rsplitn(2, ':');
+            let time_spent = parts.next()?.parse().ok()?;
+            parts.next()?;
+            Some(time_spent)
+        };
+
+        let mut rest = entries;
+        loop {
+            let mut parts = rest.rsplitn(2, ',');
+            let time_entry = parts.next()?;
+            match parts.next() {
+                Some(prefix)
+                    if time_spent_of(prefix).is_some()
+                        && parse_time_entry(time_entry).is_some() =>
+                {
+                    rest = prefix
+                }
+                _ => break,
+            }
+        }
+        if rest.len() == entries.len() {
+            return None;
+        }
+
+        let source_file = rest.rsplitn(2, ':').nth(1)?;
+        if source_file
+            .split(',')
+            .skip(1)
+            .any(|part| parse_time_entry(part).is_some())
+        {
+            return None;
+        }
+        Some(FileEntry {
+            source_file,
+            time_spent: time_spent_of(rest)?,
+            timeline: &entries[rest.len() + 1..],
+            status,
+        })
+    }
+
+    /// Reports the events of this entry to `visitor`.
+    fn report(&self, visitor: &mut dyn NoteVisitor<'a>) {
+        visitor.file(self.source_file, self.time_spent);
+        for time_entry in self.timeline.split(',') {
+            let (epoch, seconds) =
+                parse_time_entry(time_entry).expect("Timeline entry already validated");
+            visitor.timeline(epoch, seconds);
+        }
+        visitor.file_end(self.status);
     }
-    if timeline.len() == 0 {
-        return Err(FileNoteParseError::NotEnoughEntries);
+}
+
+/// Builds `CommitNote`s and `FileNote`s from the events of a `NoteVisitor`.
+/// Invalid lines are collected in `diagnostics` when `lenient`,
+/// otherwise they abort the parse.
+/// Paths of notes with version `ESCAPED_PATHS_VERSION` or later are unescaped.
+/// Paths that are not valid UTF-8 keep their bytes in `FileNote::raw_path`,
+/// and have the invalid bytes replaced with `U+FFFD` in `FileNote::source_file`.
+struct NoteBuilder<'a> {
+    note: Option<CommitNote<'a>>,
+    files: Vec<FileNote<'a>>,
+    file: Option<FileNote<'a>>,
+    lenient: bool,
+    diagnostics: Vec<CommitNoteParseError>,
+}
+
+impl<'a> NoteBuilder<'a> {
+    fn new() -> Self {
+        Self {
+            note: None,
+            files: Vec::new(),
+            file: None,
+            lenient: false,
+            diagnostics: Vec::new(),
+        }
     }
 
-    let note = FileNote {
-        source_file: file_name,
-        time_spent: time_spent.parse::<seconds>().map_err(|err| {
-            FileNoteParseError::InvalidTotalTimespent {
-                kind: err.kind().to_owned(),
+    fn lenient() -> Self {
+        Self {
+            lenient: true,
+            ..Self::new()
+        }
+    }
+
+    fn build(self) -> CommitNote<'a> {
+        let mut note = self.note.expect("Header already visited");
+        note.files = self.files;
+        note
+    }
+}
+
+impl<'a> NoteVisitor<'a> for NoteBuilder<'a> {
+    fn header(&mut self, version: u32, total: seconds) {
+        self.note = Some(CommitNote::new(version, total));
+    }
+
+    fn file(&mut self, source_file: &'a str, time_spent: seconds) {
+        let (source_file, raw_path) = match &self.note {
+            Some(note) if note.version >= ESCAPED_PATHS_VERSION => {
+                match unescape_path(source_file) {
+                    Cow::Borrowed(_) => (Cow::Borrowed(source_file), None),
+                    Cow::Owned(path) => match String::from_utf8(path) {
+                        Ok(path) => (Cow::Owned(path), None),
+                        Err(err) => (
+                            Cow::Owned(String::from_utf8_lossy(err.as_bytes()).into_owned()),
+                            Some(err.into_bytes()),
+                        ),
+                    },
+                }
             }
-        })?,
-        timeline,
-        status,
-    };
-    Ok(note)
+            _ => (Cow::Borrowed(source_file), None),
+        };
+        self.file = Some(FileNote {
+            source_file,
+            time_spent,
+            timeline: BTreeMap::new(),
+            status: FileStatus::Read,
+            raw_path,
+        });
+    }
+
+    fn timeline(&mut self, epoch: epoch, seconds: seconds) {
+        if let Some(file) = &mut self.file {
+            file.timeline.insert(epoch, seconds);
+        }
+    }
+
+    fn file_end(&mut self, status: &'a str) {
+        if let Some(mut file) = self.file.take() {
+            file.status = FileStatus::from(status);
+            self.files.push(file);
+        }
+    }
+
+    fn error(&mut self, err: CommitNoteParseError) -> Result<(), CommitNoteParseError> {
+        if self.lenient {
+            self.diagnostics.push(err);
+            Ok(())
+        } else {
+            Err(err)
+        }
+    }
+}
+
+/// Notes with this version, or later, escape file paths with `escape_path`.
+/// This makes file paths containing `,`, `:`, line breaks
+/// or bytes that are not valid UTF-8 unambiguous.
+pub const ESCAPED_PATHS_VERSION: u32 = 3;
+
+/// Escapes a file path to be written in a note
+/// with version `ESCAPED_PATHS_VERSION` or later.
+///
+/// The characters `%`, `,` and `:`, control characters
+/// and bytes that are not valid UTF-8 are written as `%XX`,
+/// where `XX` is the hexadecimal value of the byte.
+///
+/// ```
+/// use gtm::parse::*;
+/// assert_eq!(escape_path(b"src/main.rs"), "src/main.rs");
+/// assert_eq!(escape_path(b"docs/a,b:c%.md"), "docs/a%2Cb%3Ac%25.md");
+/// assert_eq!(escape_path("src/ñ.rs".as_bytes()), "src/ñ.rs");
+/// assert_eq!(escape_path(b"src/\xff.rs"), "src/%FF.rs");
+/// ```
+pub fn escape_path(path: &[u8]) -> Cow<str> {
+    fn needs_escape(c: char) -> bool {
+        c == '%' || c == ',' || c == ':' || c.is_control()
+    }
+    fn push_escaped(text: &str, escaped: &mut String) {
+        for c in text.chars() {
+            if needs_escape(c) {
+                let mut buf = [0; 4];
+                for byte in c.encode_utf8(&mut buf).bytes() {
+                    escaped.push_str(&format!("%{:02X}", byte));
+                }
+            } else {
+                escaped.push(c);
+            }
+        }
+    }
+
+    if let Ok(text) = std::str::from_utf8(path) {
+        if !text.contains(needs_escape) {
+            return Cow::Borrowed(text);
+        }
+    }
+
+    let mut escaped = String::with_capacity(path.len());
+    let mut rest = path;
+    while !rest.is_empty() {
+        match std::str::from_utf8(rest) {
+            Ok(text) => {
+                push_escaped(text, &mut escaped);
+                break;
+            }
+            Err(err) => {
+                let (valid, invalid) = rest.split_at(err.valid_up_to());
+                push_escaped(std::str::from_utf8(valid).unwrap(), &mut escaped);
+                let len = err.error_len().unwrap_or_else(|| invalid.len());
+                for byte in &invalid[..len] {
+                    escaped.push_str(&format!("%{:02X}", byte));
+                }
+                rest = &invalid[len..];
+            }
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+/// Decodes a file path escaped by `escape_path`.
+/// Malformed escape sequences are kept as they are.
+///
+/// ```
+/// use gtm::parse::*;
+/// assert_eq!(&*unescape_path("src/main.rs"), b"src/main.rs");
+/// assert_eq!(&*unescape_path("docs/a%2Cb%3Ac%25.md"), b"docs/a,b:c%.md");
+/// assert_eq!(&*unescape_path("src/%FF.rs"), b"src/\xff.rs");
+/// assert_eq!(&*unescape_path("100%"), b"100%");
+/// assert_eq!(&*unescape_path("%+F%zz"), b"%+F%zz");
+/// ```
+pub fn unescape_path(path: &str) -> Cow<[u8]> {
+    if !path.contains('%') {
+        return Cow::Borrowed(path.as_bytes());
+    }
+
+    let bytes = path.as_bytes();
+    let mut unescaped = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let byte = path
+            .get(i + 1..i + 3)
+            .filter(|hex| bytes[i] == b'%' && hex.bytes().all(|b| b.is_ascii_hexdigit()))
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match byte {
+            Some(byte) => {
+                unescaped.push(byte);
+                i += 3;
+            }
+            None => {
+                unescaped.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    Cow::Owned(unescaped)
 }
 
 #[derive(Debug, PartialEq)]
@@ -188,11 +671,134 @@ pub enum CommitNoteParseError {
     /// ```
     InvalidTotal,
 
+    /// Occurs when there is no decoder registered for the note version.
+    /// Notes written by future gtm clients are rejected,
+    /// rather than misread with the format of a known version.
+    ///
+    /// ```
+    /// use gtm::parse::*;
+    /// assert_eq!(
+    ///     parse_commit_note("[ver:7,total:60]\nsrc/main.ts:60,1585861200:60,r"),
+    ///     Err(CommitNoteParseError::UnknownVersion { version: 7 })
+    /// );
+    /// ```
+    UnknownVersion { version: u32 },
+
+    /// Occurs when a file entry cannot be parsed.
+    /// `line` is the 1-based line number of the entry within the note,
+    /// `text` is the whole line,
+    /// and `span` is the byte range within `text` of the offending component.
+    ///
+    /// ```
+    /// use gtm::parse::*;
+    /// assert_eq!(
+    ///     parse_commit_note("[ver:1,total:90]\ntext/src/char.ts:90,1585918800;90,r"),
+    ///     Err(CommitNoteParseError::InvalidFile {
+    ///         err: FileNoteParseError::InvalidTimelineFormat,
+    ///         line: 2,
+    ///         span: 20..33,
+    ///         text: "text/src/char.ts:90,1585918800;90,r".to_owned(),
+    ///     })
+    /// );
+    /// ```
     InvalidFile {
         err: FileNoteParseError,
+        line: usize,
+        span: Range<usize>,
+        text: String,
     },
 }
 
+impl CommitNoteParseError {
+    /// Returns the 1-based line number where this error occurred.
+    /// Header errors always occur in the first line.
+    pub fn line(&self) -> usize {
+        match self {
+            CommitNoteParseError::InvalidFile { line, .. } => *line,
+            _ => 1,
+        }
+    }
+}
+
+/// Formats this error in a single line, prefixed by its line number.
+/// The alternate flag (`{:#}`) renders the offending line
+/// and underlines the component that could not be parsed.
+///
+/// ```
+/// use gtm::parse::*;
+/// let err = parse_commit_note("[ver:1,total:90]\ntext/src/char.ts:90,1585918800;90,r").unwrap_err();
+/// assert_eq!(
+///     format!("{}", err),
+///     "line 2: invalid timeline entry, expected `epoch:seconds`"
+/// );
+/// assert_eq!(
+///     format!("{:#}", err),
+///     "invalid timeline entry, expected `epoch:seconds`
+///  --> line 2, column 21
+///   |
+/// 2 | text/src/char.ts:90,1585918800;90,r
+///   |                     ^^^^^^^^^^^^^"
+/// );
+/// ```
+impl fmt::Display for CommitNoteParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            CommitNoteParseError::EmptyNote => "empty note".to_owned(),
+            CommitNoteParseError::InvalidHeader => {
+                "invalid header, expected `[ver:N,total:T]`".to_owned()
+            }
+            CommitNoteParseError::InvalidVersion => "invalid version in header".to_owned(),
+            CommitNoteParseError::InvalidTotal => "invalid total in header".to_owned(),
+            CommitNoteParseError::UnknownVersion { version } => {
+                format!("unknown note version {}", version)
+            }
+            CommitNoteParseError::InvalidFile { err, .. } => err.to_string(),
+        };
+        if !f.alternate() {
+            return write!(f, "line {}: {}", self.line(), message);
+        }
+
+        write!(f, "{}", message)?;
+        match self {
+            CommitNoteParseError::InvalidFile {
+                line, span, text, ..
+            } => {
+                let gutter = " ".repeat(line.to_string().len());
+                let column = text.get(..span.start).unwrap_or(text).chars().count();
+                let width = text
+                    .get(span.clone())
+                    .map_or(1, |s| s.chars().count().max(1));
+                write!(
+                    f,
+                    "\n{} --> line {}, column {}",
+                    &gutter[1..],
+                    line,
+                    column + 1
+                )?;
+                write!(f, "\n{} |", gutter)?;
+                write!(f, "\n{} | {}", line, text)?;
+                write!(
+                    f,
+                    "\n{} | {}{}",
+                    gutter,
+                    " ".repeat(column),
+                    "^".repeat(width)
+                )
+            }
+            _ => write!(f, "\n --> line {}", self.line()),
+        }
+    }
+}
+
+impl std::error::Error for CommitNoteParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CommitNoteParseError::InvalidFile { err, .. } => Some(err),
+            _ => None,
+        }
+    }
+}
+
 /// Parses a `CommitNote`.
 ///
 /// # Examples
@@ -227,20 +833,22 @@ pub enum CommitNoteParseError {
 ///             total: 213,
 ///             files: vec![
 ///                 FileNote {
-///                     source_file: "closebrackets/src/closebrackets.ts",
+///                     source_file: "closebrackets/src/closebrackets.ts".into(),
 ///                     time_spent: 950,
 ///                     timeline: btreemap! {
 ///                         1585918800 => 510,
 ///                         1585922400 => 400,
 ///                         1585929600 => 40,
 ///                     },
-///                     status: "r",
+///                     status: FileStatus::Read,
+///                     raw_path: None,
 ///                 },
 ///                 FileNote {
-///                     source_file: "text/src/char.ts",
+///                     source_file: "text/src/char.ts".into(),
 ///                     time_spent: 90,
 ///                     timeline: btreemap! { 1585918800 => 90, },
-///                     status: "r",
+///                     status: FileStatus::Read,
+///                     raw_path: None,
 ///                 }
 ///             ],
 ///         }
@@ -268,49 +876,601 @@ pub enum CommitNoteParseError {
 ///     assert_eq!(
 ///         note.files[3],
 ///         FileNote {
-///             source_file: "demo/demo.ts",
+///             source_file: "demo/demo.ts".into(),
 ///             time_spent: 60,
 ///             timeline: btreemap! { 1585918800 => 60 },
-///             status: "r",
+///             status: FileStatus::Read,
+///             raw_path: None,
 ///         }
 ///     );
 /// ```
 pub fn parse_commit_note<'a>(message: &'a str) -> Result<CommitNote<'a>, CommitNoteParseError> {
-    lazy_static! {
-        static ref VERSION_RE: Regex = Regex::new(r"\[ver:(\d+),total:(\d+)\]").unwrap();
-    }
-    let mut lines = message.lines();
-    let mut commit_note = match lines.next() {
-        None => return Err(CommitNoteParseError::EmptyNote),
-        Some(first) => match VERSION_RE.captures_iter(first).next() {
-            None => return Err(CommitNoteParseError::InvalidHeader),
-            Some(parts) => CommitNote {
-                version: match parts[1].parse::<u32>() {
-                    Err(_) => return Err(CommitNoteParseError::InvalidVersion),
-                    Ok(value) => value,
-                },
-                total: match parts[2].parse::<seconds>() {
-                    Err(_) => return Err(CommitNoteParseError::InvalidTotal),
-                    Ok(value) => value,
-                },
-                files: Vec::new(),
-            },
-        },
-    };
-    for line in lines {
-        match parse_file_note(line) {
-            Err(err) => return Err(CommitNoteParseError::InvalidFile { err }),
-            Ok(entry) => commit_note.files.push(entry),
+    BUILTIN_VERSIONS.parse(message)
+}
+
+/// Parses a `CommitNote`, skipping the file entries that cannot be parsed.
+///
+/// Returns the `CommitNote` with the files that were successfully parsed,
+/// together with one `CommitNoteParseError::InvalidFile` for each skipped line.
+/// The header must still be valid,
+/// otherwise there is no version to recover.
+/// When lines are skipped, `total` is the time spent on the files that were kept,
+/// instead of the `total` in the header, which also counts the skipped lines.
+///
+/// ```
+/// #[macro_use] extern crate maplit;
+/// use gtm::{*, parse::*};
+/// let (note, diagnostics) = parse_commit_note_lenient("[ver:1,total:210]
+/// src/main.ts:90,1585861200:90,r
+/// src/odd.ts:60,1585861200;60,r
+/// src/lib.ts:60,1585861200:60,m").unwrap();
+/// assert_eq!(note.total, 150);
+/// assert_eq!(
+///     note.files.iter().map(|f| &*f.source_file).collect::<Vec<_>>(),
+///     vec!["src/main.ts", "src/lib.ts"]
+/// );
+/// assert_eq!(diagnostics.len(), 1);
+/// assert_eq!(diagnostics[0].line(), 3);
+/// ```
+///
+/// Invalid headers are still rejected.
+///
+/// ```
+/// use gtm::parse::*;
+/// assert_eq!(parse_commit_note_lenient("[ver:1]"), Err(CommitNoteParseError::InvalidHeader));
+/// ```
+pub fn parse_commit_note_lenient<'a>(
+    message: &'a str,
+) -> Result<(CommitNote<'a>, Vec<CommitNoteParseError>), CommitNoteParseError> {
+    BUILTIN_VERSIONS.parse_lenient(message)
+}
+
+/// Selects how strictly `parse_commit_note_with` handles invalid file entries.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ParseMode {
+    /// Rejects the whole note on the first invalid line, as `parse_commit_note` does.
+    Strict,
+
+    /// Skips invalid lines, as `parse_commit_note_lenient` does.
+    Lenient,
+}
+
+impl Default for ParseMode {
+    fn default() -> Self {
+        ParseMode::Strict
+    }
+}
+
+/// Parses a `CommitNote` according to `mode`.
+/// The returned diagnostics are always empty in `ParseMode::Strict`.
+pub fn parse_commit_note_with(
+    message: &str,
+    mode: ParseMode,
+) -> Result<(CommitNote<'_>, Vec<CommitNoteParseError>), CommitNoteParseError> {
+    BUILTIN_VERSIONS.parse_with(message, mode)
+}
+
+lazy_static! {
+    static ref BUILTIN_VERSIONS: NoteVersions = NoteVersions::default();
+}
+
+/// Decodes the file entries of notes with a given version.
+///
+/// Decoders are registered by version in `NoteVersions`.
+pub trait NoteDecoder: Send + Sync {
+    /// Decodes a single line after the header, reporting its file to `visitor`.
+    ///
+    /// Lines that do not describe a file,
+    /// *e.g.*, lines carrying metadata in newer formats,
+    /// need not report anything.
+    /// On error, also returns the byte range within `line`
+    /// of the component that could not be decoded.
+    fn decode_line<'a>(
+        &self,
+        line: &'a str,
+        visitor: &mut dyn NoteVisitor<'a>,
+    ) -> Result<(), (FileNoteParseError, Range<usize>)>;
+}
+
+/// Decodes notes with versions 1 and 2,
+/// where file paths are written as they are.
+/// Paths containing `,` or `:` are recovered with a right-anchored parse.
+pub struct PlainPathsDecoder;
+
+impl NoteDecoder for PlainPathsDecoder {
+    fn decode_line<'a>(
+        &self,
+        line: &'a str,
+        visitor: &mut dyn NoteVisitor<'a>,
+    ) -> Result<(), (FileNoteParseError, Range<usize>)> {
+        visit_file_note(line, visitor)
+    }
+}
+
+/// Decodes notes with version `ESCAPED_PATHS_VERSION`,
+/// where file paths are escaped with `escape_path`
+/// and therefore never contain `,` nor `:`.
+pub struct EscapedPathsDecoder;
+
+impl NoteDecoder for EscapedPathsDecoder {
+    fn decode_line<'a>(
+        &self,
+        line: &'a str,
+        visitor: &mut dyn NoteVisitor<'a>,
+    ) -> Result<(), (FileNoteParseError, Range<usize>)> {
+        FileEntry::from_left(line)?.report(visitor);
+        Ok(())
+    }
+}
+
+/// A registry of the note versions that can be parsed,
+/// together with the `NoteDecoder` for each version.
+///
+/// The default registry knows versions 1 to `ESCAPED_PATHS_VERSION`.
+/// Decoders for new formats can be registered on top of it.
+///
+/// ```
+/// use gtm::{*, parse::*};
+/// use std::ops::Range;
+///
+/// /// Version 4 adds `@key:value` lines with editor metadata.
+/// struct WithMetadata;
+///
+/// impl NoteDecoder for WithMetadata {
+///     fn decode_line<'a>(
+///         &self,
+///         line: &'a str,
+///         visitor: &mut dyn NoteVisitor<'a>,
+///     ) -> Result<(), (FileNoteParseError, Range<usize>)> {
+///         if line.starts_with('@') {
+///             Ok(())
+///         } else {
+///             EscapedPathsDecoder.decode_line(line, visitor)
+///         }
+///     }
+/// }
+///
+/// let message = "[ver:4,total:60]\n@app:vscode\nsrc/main.ts:60,1585861200:60,r";
+/// assert_eq!(
+///     NoteVersions::default().parse(message),
+///     Err(CommitNoteParseError::UnknownVersion { version: 4 })
+/// );
+///
+/// let mut versions = NoteVersions::default();
+/// versions.register(4, WithMetadata);
+/// let note = versions.parse(message).unwrap();
+/// assert_eq!(note.version, 4);
+/// assert_eq!(note.files.len(), 1);
+/// assert_eq!(note.files[0].source_file, "src/main.ts");
+/// ```
+pub struct NoteVersions {
+    decoders: BTreeMap<u32, Box<dyn NoteDecoder>>,
+}
+
+impl NoteVersions {
+    /// Creates a registry without any known version.
+    pub fn empty() -> Self {
+        Self {
+            decoders: BTreeMap::new(),
+        }
+    }
+
+    /// Registers `decoder` for notes with `version`,
+    /// replacing any decoder previously registered for it.
+    pub fn register<D: NoteDecoder + 'static>(&mut self, version: u32, decoder: D) -> &mut Self {
+        self.decoders.insert(version, Box::new(decoder));
+        self
+    }
+
+    /// Returns whether notes with `version` can be parsed.
+    ///
+    /// ```
+    /// use gtm::parse::*;
+    /// let versions = NoteVersions::default();
+    /// assert!(versions.is_known(1));
+    /// assert!(versions.is_known(ESCAPED_PATHS_VERSION));
+    /// assert!(!versions.is_known(0));
+    /// assert!(!versions.is_known(ESCAPED_PATHS_VERSION + 1));
+    /// ```
+    pub fn is_known(&self, version: u32) -> bool {
+        self.decoders.contains_key(&version)
+    }
+
+    /// Returns the registered versions in ascending order.
+    pub fn versions(&self) -> impl Iterator<Item = u32> + '_ {
+        self.decoders.keys().copied()
+    }
+
+    /// Parses a `CommitNote` as `parse_commit_note` does,
+    /// using the decoders of this registry.
+    pub fn parse<'a>(&self, message: &'a str) -> Result<CommitNote<'a>, CommitNoteParseError> {
+        let mut builder = NoteBuilder::new();
+        self.visit(message, &mut builder)?;
+        Ok(builder.build())
+    }
+
+    /// Parses a `CommitNote` as `parse_commit_note_lenient` does,
+    /// using the decoders of this registry.
+    ///
+    /// Notes with an unknown version are decoded with the latest registered version,
+    /// and `CommitNoteParseError::UnknownVersion` is reported as the first diagnostic.
+    ///
+    /// ```
+    /// use gtm::parse::*;
+    /// let (note, diagnostics) = NoteVersions::default()
+    ///     .parse_lenient("[ver:7,total:60]\nsrc/main.ts:60,1585861200:60,r")
+    ///     .unwrap();
+    /// assert_eq!(note.files.len(), 1);
+    /// assert_eq!(diagnostics, vec![CommitNoteParseError::UnknownVersion { version: 7 }]);
+    /// ```
+    pub fn parse_lenient<'a>(
+        &self,
+        message: &'a str,
+    ) -> Result<(CommitNote<'a>, Vec<CommitNoteParseError>), CommitNoteParseError> {
+        let mut builder = NoteBuilder::lenient();
+        self.visit(message, &mut builder)?;
+        let diagnostics = std::mem::take(&mut builder.diagnostics);
+        let mut note = builder.build();
+        // The header total also counts the skipped lines.
+        let skipped = diagnostics.iter().any(|err| match err {
+            CommitNoteParseError::InvalidFile { .. } => true,
+            _ => false,
+        });
+        if skipped {
+            note.total = note.files.iter().map(|file| file.time_spent).sum();
+        }
+        Ok((note, diagnostics))
+    }
+
+    /// Parses a `CommitNote` according to `mode`,
+    /// using the decoders of this registry.
+    pub fn parse_with<'a>(
+        &self,
+        message: &'a str,
+        mode: ParseMode,
+    ) -> Result<(CommitNote<'a>, Vec<CommitNoteParseError>), CommitNoteParseError> {
+        match mode {
+            ParseMode::Strict => self.parse(message).map(|note| (note, Vec::new())),
+            ParseMode::Lenient => self.parse_lenient(message),
+        }
+    }
+
+    /// Visits a note as `visit_commit_note` does,
+    /// using the decoders of this registry.
+    ///
+    /// Notes with an unknown version are given to `NoteVisitor::error`,
+    /// and decoded with the latest registered version if it returns `Ok`.
+    pub fn visit<'a, V: NoteVisitor<'a>>(
+        &self,
+        message: &'a str,
+        visitor: &mut V,
+    ) -> Result<(), CommitNoteParseError> {
+        let mut lines = message.lines();
+        let (version, total) = match lines.next() {
+            None => return Err(CommitNoteParseError::EmptyNote),
+            Some(first) => parse_header(first)?,
         };
+        visitor.header(version, total);
+
+        let decoder = match self.decoders.get(&version) {
+            Some(decoder) => decoder,
+            None => {
+                visitor.error(CommitNoteParseError::UnknownVersion { version })?;
+                match self.decoders.values().next_back() {
+                    Some(decoder) => decoder,
+                    None => return Err(CommitNoteParseError::UnknownVersion { version }),
+                }
+            }
+        };
+
+        for (index, line) in lines.enumerate() {
+            if let Err((err, span)) = decoder.decode_line(line, visitor) {
+                visitor.error(CommitNoteParseError::InvalidFile {
+                    err,
+                    line: index + 2,
+                    span,
+                    text: line.to_owned(),
+                })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for NoteVersions {
+    fn default() -> Self {
+        let mut versions = Self::empty();
+        versions
+            .register(1, PlainPathsDecoder)
+            .register(2, PlainPathsDecoder)
+            .register(ESCAPED_PATHS_VERSION, EscapedPathsDecoder);
+        versions
     }
-    Ok(commit_note)
+}
+
+/// Parses the `[ver:N,total:T]` header of a note into its version and total.
+/// As with the rest of the line, any text around the header is ignored.
+fn parse_header(header: &str) -> Result<(u32, seconds), CommitNoteParseError> {
+    fn digits(text: &str) -> (&str, &str) {
+        let end = text
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or_else(|| text.len());
+        text.split_at(end)
+    }
+
+    for (start, _) in header.match_indices("[ver:") {
+        let (version, rest) = digits(&header[start + "[ver:".len()..]);
+        if version.is_empty() || !rest.starts_with(",total:") {
+            continue;
+        }
+        let (total, rest) = digits(&rest[",total:".len()..]);
+        if total.is_empty() || !rest.starts_with(']') {
+            continue;
+        }
+        return Ok((
+            version
+                .parse()
+                .map_err(|_| CommitNoteParseError::InvalidVersion)?,
+            total
+                .parse()
+                .map_err(|_| CommitNoteParseError::InvalidTotal)?,
+        ));
+    }
+    Err(CommitNoteParseError::InvalidHeader)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::parse_commit_note;
+    use super::{
+        escape_path, parse_commit_note, parse_commit_note_lenient, parse_file_note, unescape_path,
+        visit_commit_note, CommitNoteParseError, FileNoteParseError, NoteVersions, NoteVisitor,
+        ESCAPED_PATHS_VERSION,
+    };
+    use crate::{epoch, seconds};
     use chrono::{DateTime, TimeZone, Utc};
 
+    const NOTES: &[&str] = &[
+        "[ver:2,total:123]",
+        "[ver:2,total:213]
+closebrackets/src/closebrackets.ts:950,1585918800:510,1585922400:400,1585929600:40,r
+text/src/char.ts:90,1585918800:90,r",
+        "[ver:1,total:4037]
+comment/src/comment.ts:2797,1585861200:354,1585875600:50,1585879200:240,1585908000:444,1585918800:1629,1585929600:80,m
+closebrackets/src/closebrackets.ts:950,1585918800:510,1585922400:400,1585929600:40,r
+text/src/char.ts:90,1585918800:90,r
+demo/demo.ts:60,1585918800:60,r
+state/src/selection.ts:40,1585918800:40,r
+highlight/src/highlight.ts:30,1585918800:30,r
+lang-javascript/src/javascript.ts:30,1585918800:30,r
+node_modules/w3c-keyname/index.d.ts:20,1585922400:20,r
+CHANGELOG.md:20,1585918800:20,r",
+        "[ver:1,total:4080]
+.gtm/terminal.app:3169,1586977200:180,1587042000:360,1588255200:150,1589302800:180,1589587200:710,1589590800:120,1589594400:540,1589598000:425,1589601600:480,1590771600:24,r
+hola.txt:797,1589587200:310,1589598000:415,1589601600:60,1590771600:12,d
+../.git/modules/home/COMMIT_EDITMSG:60,1590771600:60,r
+.zprofile:42,1588255200:30,1590771600:12,m
+lala:12,1590771600:12,d",
+    ];
+
+    #[test]
+    fn write_commit_note_round_trip() {
+        for message in NOTES {
+            let note = parse_commit_note(message).unwrap();
+            let text = note.to_string();
+            assert_eq!(&text, message);
+            assert_eq!(parse_commit_note(&text).unwrap(), note);
+        }
+    }
+
+    #[test]
+    fn write_odd_paths_round_trip() {
+        for line in &[
+            "docs/a,b.md:60,1585861200:60,r",
+            "C:odd:name:60,1585861200:60,r",
+            "a,123:60,1585861200:60,m",
+            "x:1,y:2:90,1585861200:30,1585865800:60,d",
+        ] {
+            let note = parse_file_note(line).unwrap();
+            assert_eq!(&note.to_string(), line);
+        }
+    }
+
+    #[test]
+    fn escape_path_round_trip() {
+        for path in &[
+            &b"src/main.rs"[..],
+            b"docs/a,b.md",
+            b"C:odd:name",
+            b"100%\n\r.txt",
+            b"\xff\xfe/\xc3",
+            "src/ñ,ü.rs".as_bytes(),
+        ] {
+            let escaped = escape_path(path);
+            assert!(!escaped.contains(|c| c == ',' || c == ':' || c == '\n'));
+            assert_eq!(&*unescape_path(&escaped), *path);
+        }
+    }
+
+    #[test]
+    fn escaped_paths_are_not_parsed_from_right() {
+        let line = "C:odd:name:60,1585861200:60,r";
+        let message = format!("[ver:2,total:60]\n{}", line);
+        let note = parse_commit_note(&message).unwrap();
+        assert_eq!(note.files[0].source_file, "C:odd:name");
+
+        let message = format!("[ver:{},total:60]\n{}", ESCAPED_PATHS_VERSION, line);
+        assert_eq!(parse_commit_note(&message).unwrap_err().line(), 2);
+
+        let line = "C%3Aodd%3Aname:60,1585861200:60,r";
+        let message = format!("[ver:{},total:60]\n{}", ESCAPED_PATHS_VERSION, line);
+        let note = parse_commit_note(&message).unwrap();
+        assert_eq!(note.files[0].source_file, "C:odd:name");
+        assert_eq!(note.to_string(), message);
+    }
+
+    #[test]
+    fn non_utf8_paths_round_trip() {
+        let message = format!(
+            "[ver:{},total:90]\nsrc/%FF.rs:60,1585861200:60,m\nsrc/%FE.rs:30,1585861200:30,r",
+            ESCAPED_PATHS_VERSION
+        );
+        let note = parse_commit_note(&message).unwrap();
+        assert_eq!(note.files[0].source_file, "src/\u{FFFD}.rs");
+        assert_eq!(note.files[0].path_bytes(), b"src/\xff.rs");
+        assert_eq!(note.files[1].path_bytes(), b"src/\xfe.rs");
+        assert_eq!(note.to_string(), message);
+
+        let mut merged = parse_commit_note(&message).unwrap();
+        merged.merge(note);
+        assert_eq!(merged.files.len(), 2);
+        assert_eq!(merged.files[0].time_spent, 120);
+    }
+
+    #[test]
+    fn glued_lines_are_rejected() {
+        let message = "[ver:1,total:90]\nsrc/a.ts:60,1585861200:60,rsrc/b.ts:30,1585861200:30,r";
+        match parse_commit_note(message) {
+            Err(CommitNoteParseError::InvalidFile { err, line, .. }) => {
+                assert_eq!(err, FileNoteParseError::InvalidTimelineFormat);
+                assert_eq!(line, 2);
+            }
+            other => panic!("Expected invalid file, got: {:?}", other),
+        }
+        let (note, diagnostics) = parse_commit_note_lenient(message).unwrap();
+        assert!(note.files.is_empty());
+        assert_eq!(diagnostics.len(), 1);
+    }
+
+    #[test]
+    fn unknown_versions_are_rejected() {
+        for version in &[0, ESCAPED_PATHS_VERSION + 1, 123] {
+            let message = format!("[ver:{},total:60]\nsrc/main.ts:60,1585861200:60,r", version);
+            let expected = || CommitNoteParseError::UnknownVersion { version: *version };
+            assert_eq!(parse_commit_note(&message), Err(expected()));
+            let (_note, diagnostics) = parse_commit_note_lenient(&message).unwrap();
+            assert_eq!(diagnostics, vec![expected()]);
+        }
+        assert_eq!(
+            NoteVersions::empty().parse_lenient("[ver:1,total:0]"),
+            Err(CommitNoteParseError::UnknownVersion { version: 1 })
+        );
+    }
+
+    #[derive(Default)]
+    struct Events(Vec<String>);
+
+    impl NoteVisitor<'_> for Events {
+        fn header(&mut self, version: u32, total: seconds) {
+            self.0.push(format!("header {} {}", version, total));
+        }
+
+        fn file(&mut self, source_file: &str, time_spent: seconds) {
+            self.0.push(format!("file {} {}", source_file, time_spent));
+        }
+
+        fn timeline(&mut self, epoch: epoch, seconds: seconds) {
+            self.0.push(format!("timeline {} {}", epoch, seconds));
+        }
+
+        fn file_end(&mut self, status: &str) {
+            self.0.push(format!("end {}", status));
+        }
+
+        fn error(&mut self, err: CommitNoteParseError) -> Result<(), CommitNoteParseError> {
+            self.0.push(format!("error {}", err.line()));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn visit_reports_events_in_order() {
+        let mut events = Events::default();
+        visit_commit_note(
+            "[ver:2,total:150]
+src/main.ts:90,1585861200:30,1585864800:60,r
+src/odd.ts:60,1585861200;60,r
+x:1,y:2:60,1585861200:60,m",
+            &mut events,
+        )
+        .unwrap();
+        assert_eq!(
+            events.0,
+            vec![
+                "header 2 150",
+                "file src/main.ts 90",
+                "timeline 1585861200 30",
+                "timeline 1585864800 60",
+                "end r",
+                "error 3",
+                "file x:1,y:2 60",
+                "timeline 1585861200 60",
+                "end m",
+            ]
+        );
+    }
+
+    #[derive(Default)]
+    struct TimeSpent {
+        total: seconds,
+        files: seconds,
+    }
+
+    impl NoteVisitor<'_> for TimeSpent {
+        fn header(&mut self, _version: u32, total: seconds) {
+            self.total = total;
+        }
+
+        fn file(&mut self, _source_file: &str, time_spent: seconds) {
+            self.files += time_spent;
+        }
+
+        fn error(&mut self, _err: CommitNoteParseError) -> Result<(), CommitNoteParseError> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn visit_reports_each_file_once() {
+        let mut time_spent = TimeSpent::default();
+        visit_commit_note(
+            "[ver:2,total:150]
+x:1,y:2:60,1585861200:60,m
+C:odd:name:40,1585861200:40,r
+docs/a,b.md:50,1585861200:50,r
+src/odd.ts:70,1585861200;70,r
+src/main.ts:80,1585861200:80,4
+src/lib.ts:90abc,1585861200:90,r",
+            &mut time_spent,
+        )
+        .unwrap();
+        assert_eq!(time_spent.files, time_spent.total);
+    }
+
+    #[test]
+    fn header_is_found_within_first_line() {
+        for message in &[
+            "[ver:2,total:60]",
+            " [ver:2,total:60] ",
+            "[ver:x][ver:2,total:60]",
+        ] {
+            let note = parse_commit_note(message).unwrap();
+            assert_eq!((note.version, note.total), (2, 60));
+        }
+        for message in &["[ver:2,total:60", "[ver:,total:60]", "ver:2,total:60]"] {
+            assert_eq!(
+                parse_commit_note(message),
+                Err(CommitNoteParseError::InvalidHeader)
+            );
+        }
+    }
+
+    #[test]
+    fn write_file_note_round_trip() {
+        for line in NOTES.iter().flat_map(|message| message.lines().skip(1)) {
+            assert_eq!(parse_file_note(line).unwrap().to_string(), line);
+        }
+    }
+
     #[test]
     fn test() {
         let message = r#"[ver:1,total:4080]
@@ -1,19 +1,31 @@
 use crate::{
     get_notes,
-    projects::ProjectKey,
-    projects::Projects,
-    status::{FileEvent, Timeline},
-    NotesFilter, WorkdirStatus,
+    projects::{ProjectKey, Projects},
+    seconds,
+    status::{workdir_status, TimelineConfig},
+    AppTime, Commit, NoteError, NotesFilter,
 };
-use fs::read_to_string;
 use git2::Repository;
-use serde::ser::Serializer;
+use log::*;
+use serde::{ser::Serializer, Deserialize, Serialize};
 use std::{
-    fs::{self, read_dir},
+    collections::BTreeMap,
     io::Write,
     path::{Path, PathBuf},
 };
 
+/// The time spent by a person in the commits accepted by a `NotesFilter`.
+#[derive(PartialEq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct PersonTime {
+    /// The author as `Name <email>`, resolved through the `.mailmap` of each repository,
+    /// see `Commit::identity`.
+    pub identity: String,
+    pub total: seconds,
+    pub commits: usize,
+}
+
+/// Writes the commits with notes accepted by `filter` as a JSON array.
 pub fn write_commits<W: Write, I: Iterator>(
     writer: &mut W,
     project_paths: I,
@@ -26,22 +38,71 @@ where
 
     let mut ser = serde_json::Serializer::new(writer);
     let mut seq = ser.serialize_seq(None).unwrap();
+    for_each_commit(project_paths, filter, |commit| {
+        seq.serialize_element(&commit)
+            .expect("Could not serialize commit");
+    })?;
+    seq.end().expect("Could not end serialize commits");
+
+    Ok(())
+}
+
+/// Writes the time spent by each person in the commits accepted by `filter`
+/// as a JSON array of `PersonTime`, sorted by identity.
+pub fn write_people<W: Write, I: Iterator>(
+    writer: &mut W,
+    project_paths: I,
+    filter: &NotesFilter,
+) -> Result<(), git2::Error>
+where
+    I::Item: AsRef<Path>,
+{
+    let mut people = BTreeMap::new();
+    for_each_commit(project_paths, filter, |commit| {
+        let person = people
+            .entry(commit.identity)
+            .or_insert_with(PersonTime::default);
+        person.total += commit.note.total;
+        person.commits += 1;
+    })?;
+    let people: Vec<_> = people
+        .into_iter()
+        .map(|(identity, person)| PersonTime { identity, ..person })
+        .collect();
+    serde_json::to_writer(writer, &people).expect("Could not serialize people");
+
+    Ok(())
+}
 
+/// Calls `f` with each commit with a note accepted by `filter`.
+/// Notes that cannot be read are skipped with a warning.
+fn for_each_commit<I: Iterator, F: FnMut(Commit<'static>)>(
+    project_paths: I,
+    filter: &NotesFilter,
+    mut f: F,
+) -> Result<(), git2::Error>
+where
+    I::Item: AsRef<Path>,
+{
     for path in project_paths {
         let repo = Repository::open(&path)?;
-        get_notes(
-            |c| {
-                seq.serialize_element(&c.commit)
-                    .expect("Could not serialize commit");
-            },
-            &repo,
-            path.as_ref().key(),
-            filter,
-        )
-        .unwrap();
+        for note in get_notes(&repo, path.as_ref().key(), filter)? {
+            match note {
+                Ok(note) => {
+                    for diagnostic in &note.diagnostics {
+                        warn!(
+                            "Skipping file in note for commit {}: {:#}",
+                            note.git_commit.id(),
+                            diagnostic
+                        );
+                    }
+                    f(note.commit);
+                }
+                Err(NoteError::Git(err)) => return Err(err),
+                Err(err) => warn!("Skipping note in {:?}: {:#}", path.as_ref(), err),
+            }
+        }
     }
-    seq.end().expect("Could not end serialize commits");
-
     Ok(())
 }
 
@@ -51,8 +112,12 @@ pub fn write_project_list<W: Write>(mut writer: W, projects: &Projects) {
     write!(writer, "{}", json).unwrap();
 }
 
-pub fn write_workdir_status<W: Write, I: Iterator>(writer: &mut W, project_paths: I)
-where
+pub fn write_workdir_status<W: Write, I: Iterator>(
+    writer: &mut W,
+    project_paths: I,
+    app_time: AppTime,
+    config: TimelineConfig,
+) where
     I::Item: AsRef<Path>,
 {
     use serde::ser::SerializeMap;
@@ -62,30 +127,10 @@ where
         .serialize_map(None)
         .expect("Could not start serialize workdir status");
     for project in project_paths {
-        let mut path = PathBuf::new();
-        path.push(&project);
-        path.push(".gtm");
-        let entries = read_dir(path).unwrap();
-        let mut events = Vec::new();
-        for entry in entries {
-            let entry = entry.unwrap();
-            let path = entry.path();
-            if !path.is_dir() && path.extension().unwrap() == "event" {
-                let ts: &str = path.file_stem().unwrap().to_str().unwrap();
-                let ts = ts.parse().unwrap();
-                let filepath = read_to_string(path).unwrap();
-                let fe = FileEvent::new(ts, filepath.as_ref());
-                events.push(fe);
-            }
+        let ws = workdir_status(project.as_ref(), app_time, config);
+        for warning in &ws.warnings {
+            warn!("Warning in {:?}: {}", project.as_ref(), warning);
         }
-        events.sort_by_key(|k| k.timestamp);
-        let cn = Timeline::from_events(&events).commit_note();
-        let ws = WorkdirStatus {
-            total: cn.total,
-            label: "TBD".to_string(),
-            commit_note: cn,
-        };
-
         map.serialize_entry(&project.key(), &ws)
             .expect("Write workdir status failed");
     }
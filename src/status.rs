@@ -1,6 +1,19 @@
 
-use crate::{seconds, epoch, FileNote, CommitNote};
-use std::collections::{BTreeMap, HashMap};
+use crate::{
+    epoch, projects::Projects, seconds, AppTime, CommitNote, FileNote, FileStatus, HeadStatus,
+    WorkdirStatus,
+};
+use git2::{BranchType, ErrorCode, Repository, Status, StatusOptions};
+use log::warn;
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    env, fmt, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
 #[derive(Debug)]
 pub struct FileEvent {
@@ -15,6 +28,538 @@ impl FileEvent {
             filename: filename.to_owned(),
         }
     }
+
+    /// Returns the path of the file, relative to the working directory.
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+
+    /// Saves this event in the `.gtm` directory of `workdir`,
+    /// creating the directory if needed.
+    ///
+    /// Each event is saved in its own file, whose content is the file path.
+    /// The first event in a second is saved in `<timestamp>.event`,
+    /// and the next ones in `<timestamp>.<n>.event`.
+    /// The event is first written to a temporary file in `.gtm`,
+    /// which is then published under its event file name with `fs::hard_link`.
+    /// This way readers never see a partially written event file,
+    /// and concurrent recorders never overwrite each other's events.
+    /// An event for a file already recorded in the same second is a duplicate
+    /// and is not saved again.
+    ///
+    /// Returns the path of the event file, or `None` when the event is a duplicate.
+    ///
+    /// ```
+    /// use gtm::status::*;
+    /// let workdir = tempfile::tempdir().unwrap();
+    /// let event = FileEvent::new(1589673491, "src/main.rs");
+    /// let path = event.save(workdir.path()).unwrap().unwrap();
+    /// assert!(path.ends_with(".gtm/1589673491.event"));
+    /// assert_eq!(event.save(workdir.path()).unwrap(), None);
+    /// let path = FileEvent::new(1589673491, "src/lib.rs").save(workdir.path()).unwrap().unwrap();
+    /// assert!(path.ends_with(".gtm/1589673491.1.event"));
+    /// assert_eq!(std::fs::read_to_string(path).unwrap(), "src/lib.rs");
+    ///
+    /// let events = read_event_files(workdir.path()).unwrap();
+    /// assert_eq!(events.len(), 2);
+    /// let files = std::fs::read_dir(workdir.path().join(".gtm")).unwrap();
+    /// assert_eq!(files.count(), 2);
+    /// ```
+    pub fn save(&self, workdir: &Path) -> io::Result<Option<PathBuf>> {
+        let gtm_dir = workdir.join(".gtm");
+        fs::create_dir_all(&gtm_dir)?;
+        let temp = gtm_dir.join(format!(
+            "{}.{}.{}.tmp",
+            self.timestamp,
+            process::id(),
+            TEMP_FILES.fetch_add(1, Ordering::Relaxed)
+        ));
+        let result = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp)?
+            .write_all(self.filename.as_bytes())
+            .and_then(|_| self.publish(&gtm_dir, &temp));
+        if let Err(err) = fs::remove_file(&temp) {
+            warn!("Could not remove temporary event file {:?}: {}", temp, err);
+        }
+        result
+    }
+
+    /// Links the fully written `temp` file to the first free event file name
+    /// in `gtm_dir` for this timestamp.
+    fn publish(&self, gtm_dir: &Path, temp: &Path) -> io::Result<Option<PathBuf>> {
+        let mut index = 0;
+        loop {
+            let path = if index == 0 {
+                gtm_dir.join(format!("{}.event", self.timestamp))
+            } else {
+                gtm_dir.join(format!("{}.{}.event", self.timestamp, index))
+            };
+            match fs::hard_link(temp, &path) {
+                Ok(()) => return Ok(Some(path)),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                    match fs::read_to_string(&path) {
+                        Ok(content) if content == self.filename => return Ok(None),
+                        Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+                        _ => {}
+                    }
+                }
+                Err(err) => return Err(err),
+            }
+            index += 1;
+        }
+    }
+}
+
+/// Counts the temporary files created by `FileEvent::save` in this process,
+/// so that concurrent saves never share a temporary file.
+static TEMP_FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// Represents the errors that prevent recording a file event.
+#[derive(Debug)]
+pub enum RecordError {
+    /// Occurs when the path cannot be resolved or the event cannot be saved.
+    Io(io::Error, PathBuf),
+
+    /// Occurs when the path is not within the working directory of a git repository.
+    Git(git2::Error),
+
+    /// Occurs when the repository at this path is not initialized by gtm.
+    NotInitialized(PathBuf),
+
+    /// Occurs when the path is not valid UTF-8.
+    InvalidPath(PathBuf),
+
+    /// Occurs when the application name has no alphanumerics, *e.g.*, `..`,
+    /// or contains characters other than alphanumerics, `-`, `_` and `.`.
+    InvalidApp(String),
+
+    /// Occurs when inotify cannot be initialized or its events cannot be read.
+    Watch(io::Error),
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecordError::Io(err, path) => write!(f, "Io error {:?} : {}", path, err),
+            RecordError::Git(err) => write!(f, "Git2 error: {}", err),
+            RecordError::NotInitialized(path) => {
+                write!(f, "Project {:?} is not initialized by gtm", path)
+            }
+            RecordError::InvalidPath(path) => write!(f, "Path {:?} is not valid UTF-8", path),
+            RecordError::InvalidApp(app) => write!(f, "Invalid application name {:?}", app),
+            RecordError::Watch(err) => write!(f, "Could not watch files: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for RecordError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RecordError::Io(err, _) | RecordError::Watch(err) => Some(err),
+            RecordError::Git(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<git2::Error> for RecordError {
+    fn from(err: git2::Error) -> Self {
+        RecordError::Git(err)
+    }
+}
+
+/// Records an event for the file at `path` at `timestamp`.
+///
+/// The event is saved with `FileEvent::save` in the working directory
+/// of the git repository containing `path`,
+/// which must be initialized in `projects`.
+/// The file path in the event is relative to the working directory.
+///
+/// Returns the path of the event file, or `None` when the event is a duplicate.
+pub fn record_event(
+    projects: &Projects,
+    path: &Path,
+    timestamp: epoch,
+) -> Result<Option<PathBuf>, RecordError> {
+    let (workdir, path) = project_workdir(projects, path)?;
+    let filename = path
+        .strip_prefix(&workdir)
+        .ok()
+        .and_then(Path::to_str)
+        .ok_or_else(|| RecordError::InvalidPath(path.clone()))?;
+    FileEvent::new(timestamp, filename)
+        .save(&workdir)
+        .map_err(|err| RecordError::Io(err, workdir.join(".gtm")))
+}
+
+/// Records an event for the application `app`, e.g., `terminal`, at `timestamp`.
+///
+/// The event is saved like `record_event` does for a file,
+/// in the project containing `path`,
+/// but for the file `.gtm/<app>.app`.
+/// Application time is then told apart from file time by `FileNote::app`.
+///
+/// Returns the path of the event file, or `None` when the event is a duplicate.
+pub fn record_app_event(
+    projects: &Projects,
+    path: &Path,
+    app: &str,
+    timestamp: epoch,
+) -> Result<Option<PathBuf>, RecordError> {
+    let valid = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.';
+    if !app.chars().any(|c| c.is_ascii_alphanumeric()) || !app.chars().all(valid) {
+        return Err(RecordError::InvalidApp(app.to_owned()));
+    }
+
+    let (workdir, _) = project_workdir(projects, path)?;
+    FileEvent::new(timestamp, &format!(".gtm/{}.app", app))
+        .save(&workdir)
+        .map_err(|err| RecordError::Io(err, workdir.join(".gtm")))
+}
+
+/// Returns the canonical working directory of the project containing `path`,
+/// together with `path` resolved by `resolve_path`.
+///
+/// Projects are matched by their canonical path,
+/// so that a project initialized through a symbolic link is found as well.
+fn project_workdir(projects: &Projects, path: &Path) -> Result<(PathBuf, PathBuf), RecordError> {
+    let (path, existing) =
+        resolve_path(path).map_err(|err| RecordError::Io(err, path.to_owned()))?;
+    let repo = Repository::discover(&existing)?;
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| git2::Error::from_str("Cannot record events in a bare repository"))?;
+    let workdir = workdir
+        .canonicalize()
+        .map_err(|err| RecordError::Io(err, workdir.to_owned()))?;
+    let initialized = projects.contains_project(&workdir)
+        || projects
+            .keys()
+            .any(|key| key.canonicalize().map_or(false, |key| key == workdir));
+    if !initialized {
+        return Err(RecordError::NotInitialized(workdir));
+    }
+    Ok((workdir, path))
+}
+
+/// Returns `path` as an absolute path with symbolic links resolved,
+/// together with its longest ancestor that exists.
+///
+/// Only the part of `path` that exists is canonicalized,
+/// so that events for deleted files can still be recorded.
+fn resolve_path(path: &Path) -> io::Result<(PathBuf, PathBuf)> {
+    let path = if path.is_absolute() {
+        path.to_owned()
+    } else {
+        env::current_dir()?.join(path)
+    };
+    let mut existing = path.as_path();
+    let mut missing = Vec::new();
+    loop {
+        match existing.canonicalize() {
+            Ok(canonical) => {
+                let resolved = missing
+                    .iter()
+                    .rev()
+                    .fold(canonical.clone(), |path, name| path.join(name));
+                return Ok((resolved, canonical));
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                match (existing.file_name(), existing.parent()) {
+                    (Some(name), Some(parent)) => {
+                        missing.push(name);
+                        existing = parent;
+                    }
+                    _ => return Err(err),
+                }
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Represents a pending event file that cannot be read.
+#[derive(Debug)]
+pub enum EventFileError {
+    /// Occurs when the name of an event file is not `<timestamp>.event`
+    /// nor `<timestamp>.<n>.event`, with a timestamp after the Unix epoch.
+    InvalidFileName(PathBuf),
+
+    /// Occurs when the event file at the given path has no file path.
+    /// Event files are published whole by `FileEvent::save`,
+    /// so an empty event file is never completed.
+    EmptyFile(PathBuf),
+
+    /// Occurs when the `.gtm` directory or an event file at the given path cannot be read.
+    Io(io::Error, PathBuf),
+}
+
+impl fmt::Display for EventFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EventFileError::InvalidFileName(path) => {
+                write!(f, "Invalid event file name: {:?}", path)
+            }
+            EventFileError::EmptyFile(path) => write!(f, "Empty event file: {:?}", path),
+            EventFileError::Io(err, path) => write!(f, "Io error {:?} : {}", path, err),
+        }
+    }
+}
+
+impl std::error::Error for EventFileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EventFileError::InvalidFileName(_) | EventFileError::EmptyFile(_) => None,
+            EventFileError::Io(err, _) => Some(err),
+        }
+    }
+}
+
+/// The pending events of a working directory,
+/// together with the path of the event file each one was read from.
+pub type EventFiles = Vec<(PathBuf, FileEvent)>;
+
+/// Reads the pending events in the `.gtm` directory of `workdir`.
+///
+/// Each event is stored in a `<timestamp>.event` or `<timestamp>.<n>.event` file
+/// whose content is the path of the file relative to `workdir`.
+/// Returns the events ordered by timestamp,
+/// together with the path of the file each one was read from.
+/// A missing `.gtm` directory has no events.
+///
+/// Fails on the first event file that cannot be read,
+/// see `read_event_files_lenient` to skip them instead.
+pub fn read_event_files(workdir: &Path) -> Result<EventFiles, EventFileError> {
+    let (events, errors) = read_event_files_lenient(workdir)?;
+    match errors.into_iter().next() {
+        Some(err) => Err(err),
+        None => Ok(events),
+    }
+}
+
+/// Reads the pending events in the `.gtm` directory of `workdir`,
+/// like `read_event_files`,
+/// but skips the event files that cannot be read.
+///
+/// Returns the events read, together with an error for each event file skipped.
+/// Fails only when the `.gtm` directory itself cannot be read.
+/// Files without the `event` extension, and directories, are not event files.
+pub fn read_event_files_lenient(
+    workdir: &Path,
+) -> Result<(EventFiles, Vec<EventFileError>), EventFileError> {
+    let gtm_dir = workdir.join(".gtm");
+    let entries = match fs::read_dir(&gtm_dir) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok((Vec::new(), Vec::new())),
+        entries => entries.map_err(|err| EventFileError::Io(err, gtm_dir.clone()))?,
+    };
+
+    let mut events = Vec::new();
+    let mut errors = Vec::new();
+    for entry in entries {
+        let path = match entry {
+            Ok(entry) => entry.path(),
+            Err(err) => {
+                errors.push(EventFileError::Io(err, gtm_dir.clone()));
+                continue;
+            }
+        };
+        if path.is_dir() || path.extension().map_or(true, |ext| ext != "event") {
+            continue;
+        }
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(parse_event_file_name);
+        let (timestamp, index) = match name {
+            Some(name) => name,
+            None => {
+                errors.push(EventFileError::InvalidFileName(path));
+                continue;
+            }
+        };
+        match fs::read_to_string(&path) {
+            Ok(content) => match content.lines().next().filter(|line| !line.is_empty()) {
+                Some(filename) => events.push((index, path, FileEvent::new(timestamp, filename))),
+                None => errors.push(EventFileError::EmptyFile(path)),
+            },
+            Err(err) => errors.push(EventFileError::Io(err, path)),
+        }
+    }
+    events.sort_by_key(|(index, _, event)| (event.timestamp, *index));
+    let events = events
+        .into_iter()
+        .map(|(_, path, event)| (path, event))
+        .collect();
+    errors.sort_by_cached_key(ToString::to_string);
+    Ok((events, errors))
+}
+
+/// Parses the timestamp and index of an event file
+/// named `<timestamp>.event`, with index 0, or `<timestamp>.<index>.event`.
+fn parse_event_file_name(name: &str) -> Option<(epoch, u32)> {
+    let stem = name.strip_suffix(".event")?;
+    let mut parts = stem.splitn(2, '.');
+    let timestamp = parts.next()?.parse::<epoch>().ok().filter(|t| *t > 0)?;
+    let index = match parts.next() {
+        Some(index) => index.parse().ok()?,
+        None => 0,
+    };
+    Some((timestamp, index))
+}
+
+/// Returns the branch and commit checked out in `repo`,
+/// and how far the branch is from its upstream.
+///
+/// A branch without commits yet has only its `branch` set.
+pub fn head_status(repo: &Repository) -> Result<HeadStatus, git2::Error> {
+    let head = match repo.head() {
+        Ok(head) => head,
+        Err(err) if err.code() == ErrorCode::UnbornBranch => {
+            let head = repo.find_reference("HEAD")?;
+            let branch = head
+                .symbolic_target()
+                .map(|target| target.trim_start_matches("refs/heads/").to_owned());
+            return Ok(HeadStatus {
+                branch,
+                ..HeadStatus::default()
+            });
+        }
+        Err(err) => return Err(err),
+    };
+    let commit = head.peel_to_commit()?;
+    let short_id = commit.as_object().short_id()?;
+    let mut status = HeadStatus {
+        branch: None,
+        short_id: short_id.as_str().map(ToOwned::to_owned),
+        subject: commit.summary().map(ToOwned::to_owned),
+        ahead: None,
+        behind: None,
+    };
+
+    if head.is_branch() {
+        status.branch = head.shorthand().map(ToOwned::to_owned);
+        let upstream = status
+            .branch
+            .as_ref()
+            .and_then(|name| repo.find_branch(name, BranchType::Local).ok())
+            .and_then(|branch| branch.upstream().ok())
+            .and_then(|upstream| upstream.get().target());
+        if let Some(upstream) = upstream {
+            let (ahead, behind) = repo.graph_ahead_behind(commit.id(), upstream)?;
+            status.ahead = Some(ahead);
+            status.behind = Some(behind);
+        }
+    }
+    Ok(status)
+}
+
+/// Sets the status of each file in `note` from the status of the working directory of `repo`.
+///
+/// A file with staged or unstaged changes, including an untracked file, is `FileStatus::Modified`,
+/// a deleted file is `FileStatus::Deleted`,
+/// and a file without changes is `FileStatus::Read`.
+/// Applications, see `FileNote::app`, are left untouched.
+pub fn mark_file_status(repo: &Repository, note: &mut CommitNote) -> Result<(), git2::Error> {
+    let mut options = StatusOptions::new();
+    options.include_untracked(true).recurse_untracked_dirs(true);
+    let statuses: HashMap<String, Status> = repo
+        .statuses(Some(&mut options))?
+        .iter()
+        .filter_map(|entry| Some((entry.path()?.to_owned(), entry.status())))
+        .collect();
+
+    for file in note.files.iter_mut().filter(|file| file.app().is_none()) {
+        let status = statuses
+            .get(file.source_file.as_ref())
+            .copied()
+            .unwrap_or_else(Status::empty);
+        file.status = if status.intersects(Status::WT_DELETED | Status::INDEX_DELETED) {
+            FileStatus::Deleted
+        } else if status.intersects(
+            Status::WT_NEW
+                | Status::WT_MODIFIED
+                | Status::WT_RENAMED
+                | Status::WT_TYPECHANGE
+                | Status::INDEX_NEW
+                | Status::INDEX_MODIFIED
+                | Status::INDEX_RENAMED
+                | Status::INDEX_TYPECHANGE,
+        ) {
+            FileStatus::Modified
+        } else {
+            FileStatus::Read
+        };
+    }
+    Ok(())
+}
+
+/// Computes the status of the pending events in the `.gtm` directory of `workdir`,
+/// and the branch and commit checked out in it.
+/// The status of each file is set by `mark_file_status`.
+///
+/// The status of a project never fails:
+/// event files that cannot be read are skipped,
+/// and reported in `WorkdirStatus::warnings` instead,
+/// as are the repository, HEAD or git status when they cannot be read.
+///
+/// The time spent in applications is reported according to `app_time`,
+/// and the time of the events is attributed according to `config`.
+pub fn workdir_status(
+    workdir: &Path,
+    app_time: AppTime,
+    config: TimelineConfig,
+) -> WorkdirStatus<'static> {
+    let mut warnings = Vec::new();
+    let repo = match Repository::open(workdir) {
+        Ok(repo) => Some(repo),
+        Err(err) => {
+            warnings.push(format!("Could not open repository: {}", err));
+            None
+        }
+    };
+    let head = match repo.as_ref().map(head_status) {
+        Some(Ok(head)) => head,
+        Some(Err(err)) => {
+            warnings.push(format!("Could not read HEAD: {}", err));
+            HeadStatus::default()
+        }
+        None => HeadStatus::default(),
+    };
+    let events: Vec<FileEvent> = match read_event_files_lenient(workdir) {
+        Ok((events, errors)) => {
+            warnings.extend(errors.iter().map(ToString::to_string));
+            events.into_iter().map(|(_, event)| event).collect()
+        }
+        Err(err) => {
+            warnings.push(err.to_string());
+            Vec::new()
+        }
+    };
+    let mut commit_note = match Timeline::from_events_with(&events, config) {
+        Ok(timeline) => timeline.commit_note().into_owned(),
+        Err(err) => {
+            warnings.push(err.to_string());
+            CommitNote::new(1, 0)
+        }
+    };
+    app_time.apply(&mut commit_note);
+    if let Some(repo) = &repo {
+        if let Err(err) = mark_file_status(repo, &mut commit_note) {
+            warnings.push(format!("Could not read git status: {}", err));
+        }
+    }
+
+    WorkdirStatus {
+        total: commit_note.total,
+        app_time: commit_note.app_time(),
+        file_time: commit_note.file_time(),
+        label: head.to_string(),
+        head,
+        commit_note,
+        warnings,
+    }
 }
 
 /// Given a Unix epoch,
@@ -58,24 +603,94 @@ pub fn down_to_hour(timestamp: epoch) -> epoch {
     (timestamp / 3600) * 3600
 }
 
+/// Given a Unix epoch, returns a Unix epoch rounded down to a multiple of `width` seconds.
+/// It is used to create bins of any width.
+///
+/// ```
+/// use gtm::status::*;
+/// assert_eq!(down_to(1589673494, 60), down_to_minute(1589673494));
+/// assert_eq!(down_to(1589673494, 300), 1589673300);
+/// ```
+pub fn down_to(timestamp: epoch, width: seconds) -> epoch {
+    (timestamp / width as epoch) * width as epoch
+}
+
+/// Settings used by `Timeline` to attribute time to the files of each bin.
+///
+/// The default settings credit 60 seconds to each minute with events,
+/// split among the files of that minute.
+///
+/// ```
+/// use gtm::status::*;
+/// assert_eq!(
+///     TimelineConfig::default(),
+///     TimelineConfig { bin_width: 60, credit: 60, idle_timeout: None }
+/// );
+/// ```
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TimelineConfig {
+    /// The width in seconds of each bin. Events are grouped in bins of this width.
+    /// It must not be zero.
+    pub bin_width: seconds,
+
+    /// The seconds credited to each bin with events.
+    pub credit: seconds,
+
+    /// When set, the time until the next bin with events is carried forward
+    /// and credited to the current bin, as long as it does not exceed this timeout.
+    /// Longer gaps are considered idle time,
+    /// and the current bin is credited with `credit` only.
+    pub idle_timeout: Option<seconds>,
+}
+
+impl Default for TimelineConfig {
+    fn default() -> Self {
+        Self {
+            bin_width: 60,
+            credit: 60,
+            idle_timeout: None,
+        }
+    }
+}
+
 /// ```
 /// assert_eq!("", "");
 /// ```
 pub struct TimelineBin<'a> {
     filemap: HashMap<&'a str, usize>,
     count: usize,
+    credit: seconds,
 }
 
 impl<'a> TimelineBin<'a> {
     /// Creates a new `TimelineBin`.
     /// When created, the bin will be empty, *i.e.*, there are no files in it.
     pub fn new() -> TimelineBin<'a> {
+        TimelineBin::with_credit(60)
+    }
+
+    /// Creates a new empty `TimelineBin` that splits `credit` seconds among its files.
+    ///
+    /// ```
+    /// use gtm::status::*;
+    /// let mut bin = TimelineBin::with_credit(300);
+    /// bin.append("src/main.rs");
+    /// bin.append("src/lib.rs");
+    /// assert_eq!(bin.timespent("src/main.rs"), 150);
+    /// ```
+    pub fn with_credit(credit: seconds) -> TimelineBin<'a> {
         TimelineBin {
             filemap: HashMap::new(),
             count: 0,
+            credit,
         }
     }
 
+    /// Returns the seconds credited to this bin.
+    pub fn credit(&self) -> seconds {
+        self.credit
+    }
+
     /// ```
     /// use gtm::status::*;
     /// let mut bin = TimelineBin::new();
@@ -101,23 +716,113 @@ impl<'a> TimelineBin<'a> {
     /// let mut bin = TimelineBin::new();
     /// bin.timespent("src/not-present.rs");
     /// ```
+    ///
+    /// The time spent in each file is the one given by `apportion`,
+    /// so that the files of a bin always add up to its credit.
     pub fn timespent(self: &Self, filepath: &str) -> seconds {
-        let count = self
+        self.apportion()
+            .into_iter()
+            .find(|(file, _)| *file == filepath)
+            .map(|(_, seconds)| seconds)
+            .expect("File not present in bin")
+    }
+
+    /// Splits the credit of this bin among its files,
+    /// proportionally to the number of events of each file.
+    ///
+    /// Uses the largest remainder method,
+    /// so that the time spent in all files adds up exactly to the credit.
+    /// Each file is given the integer part of its share,
+    /// and the seconds left are given one by one to the files with the largest remainders.
+    /// Ties are broken by file path.
+    /// Returns the files ordered by path.
+    ///
+    /// ```
+    /// use gtm::status::*;
+    /// let mut bin = TimelineBin::new();
+    /// for file in &["a.rs", "b.rs", "c.rs", "d.rs", "e.rs", "f.rs", "g.rs"] {
+    ///     bin.append(file);
+    /// }
+    /// let shares = bin.apportion();
+    /// assert_eq!(shares.iter().map(|(_, seconds)| seconds).sum::<u32>(), 60);
+    /// assert_eq!(shares[0], ("a.rs", 9));
+    /// assert_eq!(shares[3], ("d.rs", 9));
+    /// assert_eq!(shares[4], ("e.rs", 8));
+    ///
+    /// let mut bin = TimelineBin::new();
+    /// bin.append("a.rs");
+    /// bin.append("b.rs");
+    /// bin.append("b.rs");
+    /// bin.append("b.rs");
+    /// bin.append("b.rs");
+    /// bin.append("b.rs");
+    /// bin.append("c.rs");
+    /// assert_eq!(bin.apportion(), vec![("a.rs", 9), ("b.rs", 43), ("c.rs", 8)]);
+    /// ```
+    pub fn apportion(&self) -> Vec<(&'a str, seconds)> {
+        let credit = self.credit as usize;
+        let mut shares: Vec<(&'a str, seconds, usize)> = self
             .filemap
-            .get(&filepath)
-            .expect("File not present in bin");
-        (60 * count / self.count) as seconds
+            .iter()
+            .map(|(file, count)| {
+                let share = credit * count;
+                (*file, (share / self.count) as seconds, share % self.count)
+            })
+            .collect();
+
+        let apportioned: usize = shares.iter().map(|(_, seconds, _)| *seconds as usize).sum();
+        shares.sort_by(|(a, _, a_rem), (b, _, b_rem)| b_rem.cmp(a_rem).then(a.cmp(b)));
+        for share in shares.iter_mut().take(credit - apportioned) {
+            share.1 += 1;
+        }
+
+        let mut shares: Vec<(&'a str, seconds)> = shares
+            .into_iter()
+            .map(|(file, seconds, _)| (file, seconds))
+            .collect();
+        shares.sort();
+        shares
+    }
+}
+
+/// Represents the errors that prevent building a `Timeline`.
+#[derive(PartialEq, Debug)]
+pub enum TimelineError {
+    /// Occurs when the `TimelineConfig::bin_width` is zero.
+    InvalidBinWidth,
+
+    /// Occurs when an event is not after the Unix epoch.
+    InvalidTimestamp { timestamp: epoch, filename: String },
+}
+
+impl fmt::Display for TimelineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimelineError::InvalidBinWidth => write!(f, "Timeline bin width must not be zero"),
+            TimelineError::InvalidTimestamp {
+                timestamp,
+                filename,
+            } => write!(
+                f,
+                "Invalid timestamp {} for event of {}",
+                timestamp, filename
+            ),
+        }
     }
 }
 
+impl std::error::Error for TimelineError {}
+
 pub struct Timeline<'a> {
-    timeline: HashMap<epoch, TimelineBin<'a>>,
+    timeline: BTreeMap<epoch, TimelineBin<'a>>,
+    config: TimelineConfig,
 }
 
 impl<'a> Timeline<'a> {
-    fn new() -> Timeline<'a> {
+    fn new(config: TimelineConfig) -> Timeline<'a> {
         Timeline {
-            timeline: HashMap::new(),
+            timeline: BTreeMap::new(),
+            config,
         }
     }
 
@@ -129,39 +834,152 @@ impl<'a> Timeline<'a> {
     ///     FileEvent::new(1589673491, "src/file1.ts"),
     ///     FileEvent::new(1589673601, "test/test1.ts"),
     /// ];
-    /// Timeline::from_events(&events);
+    /// Timeline::from_events(&events).unwrap();
     /// ```
     ///
-    /// The events in the list must be ordered by timestamp.
+    /// The events need not be ordered by timestamp.
+    /// Events for different files in the same second are all counted,
+    /// while duplicate events for the same file in the same second are counted once.
     ///
-    /// ```should_panic
+    /// ```
     /// use gtm::status::*;
     /// let events = vec![
+    ///     FileEvent::new(1589673601, "test/test1.ts"),
+    ///     FileEvent::new(1589673601, "test/test2.ts"),
+    ///     FileEvent::new(1589673601, "test/test2.ts"),
     ///     FileEvent::new(1589673491, "src/file1.ts"),
+    /// ];
+    /// let timeline = Timeline::from_events(&events).unwrap();
+    /// let bin = timeline.get(&1589673600).unwrap();
+    /// assert_eq!(bin.timespent("test/test1.ts"), 30);
+    /// assert_eq!(bin.timespent("test/test2.ts"), 30);
+    /// ```
+    ///
+    /// Events before the Unix epoch cannot be attributed and are rejected.
+    ///
+    /// ```
+    /// use gtm::status::*;
+    /// let events = vec![FileEvent::new(0, "src/file1.ts")];
+    /// assert_eq!(
+    ///     Timeline::from_events(&events).err(),
+    ///     Some(TimelineError::InvalidTimestamp { timestamp: 0, filename: "src/file1.ts".to_owned() })
+    /// );
+    /// ```
+    pub fn from_events(events: &'a [FileEvent]) -> Result<Timeline<'a>, TimelineError> {
+        Timeline::from_events_with(events, TimelineConfig::default())
+    }
+
+    /// Creates a `Timeline` from a list of file events,
+    /// attributing time according to `config`.
+    ///
+    /// The default `TimelineConfig` credits one minute to each minute with events,
+    /// split among its files.
+    ///
+    /// ```
+    /// use gtm::status::*;
+    /// let events = vec![
+    ///     FileEvent::new(1589673491, "src/file1.ts"),
+    ///     FileEvent::new(1589673494, "src/file2.ts"),
     ///     FileEvent::new(1589673601, "test/test1.ts"),
-    ///     FileEvent::new(1589673600, "test/test2.ts"),
     /// ];
-    /// Timeline::from_events(&events);
+    /// let timeline = Timeline::from_events_with(&events, TimelineConfig::default()).unwrap();
+    /// let bin = timeline.get(&1589673480).unwrap();
+    /// assert_eq!(bin.credit(), 60);
+    /// assert_eq!(bin.apportion(), vec![("src/file1.ts", 30), ("src/file2.ts", 30)]);
+    /// let bin = timeline.get(&1589673600).unwrap();
+    /// assert_eq!(bin.apportion(), vec![("test/test1.ts", 60)]);
+    /// assert!(timeline.get(&1589673540).is_none());
+    /// ```
+    ///
+    /// Wider bins group more events, and the credit is split among their files.
+    ///
     /// ```
-    pub fn from_events(events: &'a Vec<FileEvent>) -> Timeline<'a> {
-        let mut timeline = Timeline::new();
-        let mut prevepoch = 0;
-        for event in events {
-            assert!(prevepoch < event.timestamp);
-            prevepoch = event.timestamp;
+    /// use gtm::status::*;
+    /// let events = vec![
+    ///     FileEvent::new(1589673491, "src/file1.ts"),
+    ///     FileEvent::new(1589673551, "test/test1.ts"),
+    /// ];
+    /// let config = TimelineConfig { bin_width: 300, credit: 300, ..Default::default() };
+    /// let timeline = Timeline::from_events_with(&events, config).unwrap();
+    /// let bin = timeline.get(&1589673300).unwrap();
+    /// assert_eq!(bin.timespent("src/file1.ts"), 150);
+    /// assert_eq!(bin.timespent("test/test1.ts"), 150);
+    /// ```
+    ///
+    /// With an idle timeout, the time until the next bin with events is carried forward,
+    /// while gaps longer than the timeout are not credited.
+    ///
+    /// ```
+    /// use gtm::status::*;
+    /// let events = vec![
+    ///     FileEvent::new(1589673491, "src/file1.ts"),
+    ///     FileEvent::new(1589673731, "src/file2.ts"),
+    ///     FileEvent::new(1589677200, "src/file3.ts"),
+    /// ];
+    /// let config = TimelineConfig { idle_timeout: Some(900), ..Default::default() };
+    /// let timeline = Timeline::from_events_with(&events, config).unwrap();
+    /// assert_eq!(timeline.get(&1589673480).unwrap().timespent("src/file1.ts"), 240);
+    /// assert_eq!(timeline.get(&1589673720).unwrap().timespent("src/file2.ts"), 60);
+    /// assert_eq!(timeline.get(&1589677200).unwrap().timespent("src/file3.ts"), 60);
+    /// ```
+    pub fn from_events_with(
+        events: &'a [FileEvent],
+        config: TimelineConfig,
+    ) -> Result<Timeline<'a>, TimelineError> {
+        if config.bin_width == 0 {
+            return Err(TimelineError::InvalidBinWidth);
+        }
+        if let Some(event) = events.iter().find(|event| event.timestamp <= 0) {
+            return Err(TimelineError::InvalidTimestamp {
+                timestamp: event.timestamp,
+                filename: event.filename.clone(),
+            });
+        }
+
+        let mut sorted: Vec<&'a FileEvent> = events.iter().collect();
+        sorted.sort_by(|a, b| (a.timestamp, &a.filename).cmp(&(b.timestamp, &b.filename)));
+        sorted.dedup_by(|a, b| a.timestamp == b.timestamp && a.filename == b.filename);
+
+        let mut timeline = Timeline::new(config);
+        for event in sorted {
             timeline.append(event);
         }
+        timeline.carry_forward();
 
-        timeline
+        Ok(timeline)
     }
 
     /// Adds a new event to this timeline.
     fn append(self: &mut Self, event: &'a FileEvent) {
-        let minute = down_to_minute(event.timestamp);
-        let bin = self.timeline.entry(minute).or_insert_with(TimelineBin::new);
+        let start = down_to(event.timestamp, self.config.bin_width);
+        let credit = self.config.credit;
+        let bin = self
+            .timeline
+            .entry(start)
+            .or_insert_with(|| TimelineBin::with_credit(credit));
         (*bin).append(event.filename.as_str());
     }
 
+    /// Credits each bin with the time until the next bin,
+    /// when it is longer than its credit but within the idle timeout.
+    fn carry_forward(self: &mut Self) {
+        let idle_timeout = match self.config.idle_timeout {
+            Some(idle_timeout) => idle_timeout as epoch,
+            None => return,
+        };
+        let starts: Vec<epoch> = self.timeline.keys().copied().collect();
+        for pair in starts.windows(2) {
+            let gap = pair[1] - pair[0];
+            let bin = self
+                .timeline
+                .get_mut(&pair[0])
+                .expect("Bin already created");
+            if gap <= idle_timeout && gap > bin.credit as epoch {
+                bin.credit = gap as seconds;
+            }
+        }
+    }
+
     /// ```
     /// use gtm::status::*;
     /// let events = vec![
@@ -173,7 +991,7 @@ impl<'a> Timeline<'a> {
     ///     FileEvent::new(1589673732, "assets/main.css"),
     ///     FileEvent::new(1589673854, "src/file2.ts"),
     /// ];
-    /// let map = Timeline::from_events(&events);
+    /// let map = Timeline::from_events(&events).unwrap();
     ///
     /// let bin = map.get(&1589673480).unwrap();
     /// assert_eq!(bin.timespent("src/file1.ts"), 30);
@@ -206,7 +1024,7 @@ impl<'a> Timeline<'a> {
     ///     FileEvent::new(1589673658, "assets/logo.png"),
     ///     FileEvent::new(1589673732, "assets/main.css"),
     /// ];
-    /// let map = Timeline::from_events(&events);
+    /// let map = Timeline::from_events(&events).unwrap();
     ///
     /// let bin = map.get(&1589673480).unwrap();
     /// assert_eq!(bin.timespent("src/file1.ts"), 30);
@@ -223,18 +1041,31 @@ impl<'a> Timeline<'a> {
     /// let commit_note = map.commit_note();
     /// assert_eq!(commit_note.total, 180);
     /// assert!(commit_note.files.contains(
-    ///     &FileNote{ source_file: "test/test1.ts", time_spent: 20, timeline: btreemap! { 1589673600=>20}, status: "r" }
+    ///     &FileNote{ source_file: "test/test1.ts".into(), time_spent: 20, timeline: btreemap! { 1589673600=>20}, status: FileStatus::Read, raw_path: None }
     ///     ));
     /// ```
+    ///
+    /// The total is always the credit of all bins with events,
+    /// even when a bin cannot be split evenly among its files.
+    ///
+    /// ```
+    /// use gtm::status::*;
+    /// let events: Vec<_> = (0..7)
+    ///     .map(|i| FileEvent::new(1589673480 + i, &format!("src/file{}.ts", i)))
+    ///     .chain((0..3).map(|i| FileEvent::new(1589673540 + i, &format!("src/file{}.ts", i))))
+    ///     .collect();
+    /// let commit_note = Timeline::from_events(&events).unwrap().commit_note();
+    /// assert_eq!(commit_note.total, 120);
+    /// assert_eq!(commit_note.files.iter().map(|f| f.time_spent).sum::<u32>(), 120);
+    /// ```
     pub fn commit_note(self) -> CommitNote<'a> {
         let mut cn = CommitNote::new(1, 0);
-        let mut fs = HashMap::new();
+        let mut fs = BTreeMap::new();
         for (ts, bin) in &self.timeline {
-            for (f, _count) in &bin.filemap {
+            for (f, seconds) in bin.apportion() {
                 let (timespent, e) = fs.entry(f).or_insert((0, BTreeMap::new()));
                 let h = down_to_hour(*ts);
                 let t = (*e).entry(h).or_insert(0);
-                let seconds = bin.timespent(f.to_owned());
                 *timespent += seconds;
                 *t += seconds;
                 cn.total += seconds;
@@ -243,10 +1074,11 @@ impl<'a> Timeline<'a> {
 
         for (fp, tl) in fs {
             let note = FileNote {
-                source_file: fp,
-                status: "r",
+                source_file: Cow::Borrowed(fp),
+                status: FileStatus::Read,
                 time_spent: tl.0,
                 timeline: tl.1,
+                raw_path: None,
             };
             cn.files.push(note);
         }
@@ -0,0 +1,231 @@
+use crate::{
+    projects::Projects,
+    status::{FileEvent, RecordError},
+};
+use chrono::Utc;
+use git2::{Repository, Status};
+use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask};
+use log::*;
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Directories of a working directory that are never watched.
+const SKIPPED_DIRS: &[&str] = &[".git", ".gtm"];
+
+struct Project {
+    workdir: PathBuf,
+    repo: Repository,
+}
+
+/// Watches the working directories of gtm projects with inotify,
+/// and records a `FileEvent` whenever a file tracked by git is modified.
+///
+/// Untracked files, files ignored by `.gitignore`,
+/// and files within `.git/` and `.gtm/`, are skipped.
+/// Directories created after the watcher started are watched as well.
+pub struct Watcher {
+    inotify: Inotify,
+    projects: Vec<Project>,
+    /// The project and the directory relative to its working directory of each watch.
+    watches: HashMap<WatchDescriptor, (usize, PathBuf)>,
+    buffer: Vec<u8>,
+}
+
+impl Watcher {
+    /// Creates a `Watcher` without any project.
+    pub fn new() -> io::Result<Self> {
+        Ok(Self {
+            inotify: Inotify::init()?,
+            projects: Vec::new(),
+            watches: HashMap::new(),
+            buffer: vec![0; 64 * 1024],
+        })
+    }
+
+    /// Creates a `Watcher` for all the projects initialized by gtm.
+    ///
+    /// Projects that cannot be watched, *e.g.*, because they were removed,
+    /// are logged and skipped, so that the other projects are still watched.
+    pub fn from_projects(projects: &Projects) -> Result<Self, RecordError> {
+        let mut watcher = Self::new().map_err(RecordError::Watch)?;
+        for workdir in projects.keys() {
+            if let Err(err) = watcher.add_project(workdir) {
+                warn!("Skipping project {:?}: {}", workdir, err);
+            }
+        }
+        Ok(watcher)
+    }
+
+    /// Watches every directory of the working directory of the repository at `workdir`.
+    ///
+    /// Directories that cannot be watched are logged and skipped, see `watch_dir`.
+    pub fn add_project(&mut self, workdir: &Path) -> Result<(), RecordError> {
+        let repo = Repository::open(workdir)?;
+        let workdir = repo
+            .workdir()
+            .ok_or_else(|| git2::Error::from_str("Cannot watch a bare repository"))?
+            .to_owned();
+        self.projects.push(Project { workdir, repo });
+        self.watch_dir(self.projects.len() - 1, PathBuf::new(), &mut Vec::new());
+        Ok(())
+    }
+
+    /// Watches `dir`, relative to the working directory of `project`, recursively.
+    /// The files found are appended to `files`.
+    ///
+    /// Directories removed before they are watched are skipped.
+    /// Directories that cannot be watched or read are logged and skipped,
+    /// so that the rest of the project is still watched.
+    fn watch_dir(&mut self, project: usize, dir: PathBuf, files: &mut Vec<PathBuf>) {
+        let path = self.projects[project].workdir.join(&dir);
+        let wd = self.inotify.add_watch(
+            &path,
+            WatchMask::MODIFY
+                | WatchMask::CLOSE_WRITE
+                | WatchMask::MOVED_TO
+                | WatchMask::CREATE
+                | WatchMask::ONLYDIR,
+        );
+        let wd = match wd {
+            Ok(wd) => wd,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                debug!("Skipping removed directory {:?}", path);
+                return;
+            }
+            Err(err) => {
+                warn!("Cannot watch directory {:?}: {}", path, err);
+                return;
+            }
+        };
+        self.watches.insert(wd, (project, dir.clone()));
+
+        let entries = match fs::read_dir(&path) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                debug!("Skipping removed directory {:?}", path);
+                return;
+            }
+            Err(err) => {
+                warn!("Cannot read directory {:?}: {}", path, err);
+                return;
+            }
+        };
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                Err(err) => {
+                    warn!("Cannot read directory {:?}: {}", path, err);
+                    continue;
+                }
+            };
+            let is_dir = entry.file_type().map_or(false, |t| t.is_dir());
+            let child = dir.join(entry.file_name());
+            if self.is_skipped(project, &child, is_dir) {
+                continue;
+            }
+            if is_dir {
+                self.watch_dir(project, child, files);
+            } else {
+                files.push(child);
+            }
+        }
+    }
+
+    /// Returns whether `path`, relative to the working directory of `project`,
+    /// is within a skipped directory or ignored by git.
+    fn is_skipped(&self, project: usize, path: &Path, is_dir: bool) -> bool {
+        let skipped_dir = path.components().next().map_or(false, |first| {
+            SKIPPED_DIRS.iter().any(|dir| first.as_os_str() == *dir)
+        });
+        // A trailing `/` matches ignore patterns that only apply to directories.
+        let path = if is_dir {
+            path.join("")
+        } else {
+            path.to_owned()
+        };
+        skipped_dir
+            || self.projects[project]
+                .repo
+                .is_path_ignored(&path)
+                .unwrap_or(false)
+    }
+
+    /// Returns whether `path`, relative to the working directory of `project`,
+    /// is tracked by git, *i.e.*, it is in HEAD or in the index.
+    fn is_tracked(&self, project: usize, path: &Path) -> bool {
+        match self.projects[project].repo.status_file(path) {
+            Ok(status) => !status.intersects(Status::WT_NEW | Status::IGNORED),
+            Err(_) => false,
+        }
+    }
+
+    /// Blocks until files are modified,
+    /// and records an event for each of them that is tracked by git.
+    ///
+    /// Returns the working directory and the `FileEvent` of each event recorded.
+    /// Duplicate events within the same second are not recorded again.
+    /// Events that cannot be saved are logged and skipped,
+    /// so that an error only occurs when the inotify events cannot be read.
+    pub fn record_events(&mut self) -> Result<Vec<(PathBuf, FileEvent)>, RecordError> {
+        let events: Vec<(WatchDescriptor, EventMask, Option<OsString>)> = self
+            .inotify
+            .read_events_blocking(&mut self.buffer)
+            .map_err(RecordError::Watch)?
+            .map(|event| (event.wd, event.mask, event.name.map(ToOwned::to_owned)))
+            .collect();
+        let timestamp = Utc::now().timestamp();
+
+        let mut files = Vec::new();
+        for (wd, mask, name) in events {
+            if mask.contains(EventMask::Q_OVERFLOW) {
+                warn!("Too many file events, some of them were not recorded");
+            }
+            if mask.contains(EventMask::IGNORED) {
+                self.watches.remove(&wd);
+                continue;
+            }
+            let (project, path) = match (self.watches.get(&wd), name) {
+                (Some((project, dir)), Some(name)) => (*project, dir.join(name)),
+                _ => continue,
+            };
+            let is_dir = mask.contains(EventMask::ISDIR);
+            if self.is_skipped(project, &path, is_dir) {
+                continue;
+            }
+            if !is_dir {
+                files.push((project, path));
+            } else if mask.intersects(EventMask::CREATE | EventMask::MOVED_TO) {
+                let mut found = Vec::new();
+                self.watch_dir(project, path, &mut found);
+                files.extend(found.into_iter().map(|path| (project, path)));
+            }
+        }
+
+        let mut recorded = Vec::new();
+        for (project, path) in files {
+            if !self.is_tracked(project, &path) {
+                continue;
+            }
+            let workdir = &self.projects[project].workdir;
+            let filename = match path.to_str() {
+                Some(filename) => filename,
+                None => {
+                    warn!("Skipping file with non UTF-8 path {:?}", path);
+                    continue;
+                }
+            };
+            let event = FileEvent::new(timestamp, filename);
+            match event.save(workdir) {
+                Ok(Some(_)) => recorded.push((workdir.clone(), event)),
+                Ok(None) => {}
+                Err(err) => warn!("Cannot record {:?} in {:?}: {}", path, workdir, err),
+            }
+        }
+        Ok(recorded)
+    }
+}
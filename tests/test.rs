@@ -98,11 +98,26 @@ mod init_projects_tests {
 
 mod notes_tests {
 
+    use chrono::NaiveDate;
     use git2::{Oid, Repository, Signature};
     use gtm::{
-        get_notes, parse::parse_commit_note, Commit, CommitNote, FileNote, NotesFilter, GTM_REFS,
+        commit::commit_pending_note,
+        fsck::{check_notes, Problem},
+        get_notes,
+        parse::{
+            parse_commit_note, CommitNoteParseError, FileNoteParseError, NoteVersions,
+            PlainPathsDecoder,
+        },
+        projects::{ProjectKey, Projects},
+        services::write_workdir_status,
+        status::{
+            head_status, read_event_files, read_event_files_lenient, record_app_event,
+            record_event, workdir_status, EventFileError, RecordError, TimelineConfig,
+        },
+        AppTime, Commit, CommitGraph, CommitNote, DateZone, FileNote, FileStatus, NoteError,
+        NotesFilter, GTM_REFS,
     };
-    use std::error::Error;
+    use std::{error::Error, io::Write, path::Path};
     use tempfile::{tempdir, TempDir};
 
     struct TempRepo<'repo> {
@@ -152,8 +167,54 @@ mod notes_tests {
                 &self.sig,
             ))
         }
+
+        fn commit_on(
+            &self,
+            update_ref: &str,
+            author: &Signature,
+            committer: &Signature,
+            message: &str,
+            parents: &[Oid],
+        ) -> Result<TempOid, git2::Error> {
+            let tree = self.repo.find_tree(self.repo.index()?.write_tree()?)?;
+            let parents = parents
+                .iter()
+                .map(|oid| self.repo.find_commit(*oid))
+                .collect::<Result<Vec<_>, _>>()?;
+            let parents: Vec<_> = parents.iter().collect();
+            Ok(TempOid(
+                self.repo.commit(
+                    Some(update_ref),
+                    author,
+                    committer,
+                    message,
+                    &tree,
+                    &parents,
+                )?,
+                &self.repo,
+                &self.sig,
+            ))
+        }
+
+        fn commits(&self, filter: &NotesFilter) -> Result<Vec<Commit<'static>>, Box<dyn Error>> {
+            let mut commits: Vec<_> = get_notes(&self.repo, "test", filter)?
+                .map(|note| note.map(|note| note.commit))
+                .collect::<Result<_, _>>()?;
+            commits.sort_by(|a, b| a.subject.cmp(&b.subject));
+            Ok(commits)
+        }
+
+        fn subjects(&self, filter: &NotesFilter) -> Result<Vec<String>, Box<dyn Error>> {
+            Ok(self
+                .commits(filter)?
+                .into_iter()
+                .map(|c| c.subject)
+                .collect())
+        }
     }
 
+    const MINUTE_NOTE: &str = "[ver:1,total:60]\nsrc/main.ts:60,1585861200:60,m";
+
     struct TempOid<'repo>(Oid, &'repo Repository, &'repo Signature<'repo>);
 
     impl<'repo> TempOid<'repo> {
@@ -174,13 +235,7 @@ mod notes_tests {
             repo.commit("asdf")?;
         }
 
-        assert!(get_notes(
-            |_| (),
-            &repo.repo,
-            "test",
-            &NotesFilter::all()
-        )
-        .is_err());
+        assert!(get_notes(&repo.repo, "test", &NotesFilter::all()).is_err());
         Ok(())
     }
 
@@ -189,13 +244,8 @@ mod notes_tests {
         let mut repo = TempRepo::new()?;
         repo.commit("Message")?.note("[ver:1,total:0]")?;
 
-        let mut cs = Vec::new();
-        get_notes(
-            |cn| cs.push(cn),
-            &repo.repo,
-            "test",
-            &NotesFilter::all(),
-        )?;
+        let filter = NotesFilter::all();
+        let cs = get_notes(&repo.repo, "test", &filter)?.collect::<Result<Vec<_>, _>>()?;
         assert_eq!(cs.len(), 1);
         Ok(())
     }
@@ -211,15 +261,8 @@ text/src/char.ts:90,1585918800:90,r",
             )?;
         }
 
-        let mut commits = Vec::new();
-        get_notes(
-            |cn| {
-                commits.push(cn);
-            },
-            &repo.repo,
-            "test",
-            &NotesFilter::all(),
-        )?;
+        let filter = NotesFilter::all();
+        let commits = get_notes(&repo.repo, "test", &filter)?.collect::<Result<Vec<_>, _>>()?;
         assert_eq!(commits.len(), 10);
         for c in commits {
             assert_eq!(
@@ -229,20 +272,22 @@ text/src/char.ts:90,1585918800:90,r",
                     total: 213,
                     files: vec![
                         FileNote {
-                            source_file: "closebrackets/src/closebrackets.ts",
+                            source_file: "closebrackets/src/closebrackets.ts".into(),
                             time_spent: 950,
                             timeline: btreemap! {
                                 1585918800 => 510,
                                 1585922400 => 400,
                                 1585929600 => 40,
                             },
-                            status: "r",
+                            status: FileStatus::Read,
+                            raw_path: None,
                         },
                         FileNote {
-                            source_file: "text/src/char.ts",
+                            source_file: "text/src/char.ts".into(),
                             time_spent: 90,
                             timeline: btreemap! { 1585918800 => 90, },
-                            status: "r",
+                            status: FileStatus::Read,
+                            raw_path: None,
                         }
                     ],
                 }
@@ -252,6 +297,1089 @@ text/src/char.ts:90,1585918800:90,r",
         Ok(())
     }
 
+    #[test]
+    fn test_notes_filters() -> Result<(), Box<dyn Error>> {
+        let mut repo = TempRepo::new()?;
+        let first = repo.commit("First")?;
+        first.note(
+            "[ver:1,total:100]
+src/main.ts:60,1585861200:60,m
+docs/a.md:40,1585861200:40,r",
+        )?;
+        let first = first.0;
+        repo.repo
+            .branch("base", &repo.repo.find_commit(first)?, false)?;
+        let second = repo.commit("Second")?;
+        second.note("[ver:1,total:30]\ndocs/b.md:30,1585861200:30,m")?;
+        let second = second.0;
+        let other = Signature::now("Other Dev", "other@dev.io")?;
+        repo.commit_on("HEAD", &other, &other, "Third", &[second])?
+            .note("[ver:1,total:10]\nsrc/lib.ts:10,1585861200:10,m")?;
+        repo.commit_on("refs/heads/side", &repo.sig, &repo.sig, "Side", &[first])?
+            .note("[ver:1,total:20]\ndocs/c.md:20,1585861200:20,r")?;
+        let notes = |filter: &NotesFilter| repo.commits(filter);
+        let subjects = |filter: &NotesFilter| repo.subjects(filter);
+
+        assert_eq!(
+            subjects(NotesFilter::all().author("OTHER".into()))?,
+            ["Third"]
+        );
+        assert_eq!(
+            subjects(NotesFilter::all().author("test@repo.io".into()))?,
+            ["First", "Second", "Side"]
+        );
+
+        let commits = notes(NotesFilter::all().paths(vec!["src/**".into()], false))?;
+        assert_eq!(commits.len(), 2);
+        assert_eq!(
+            (commits[0].note.total, commits[0].note.files.len()),
+            (100, 2)
+        );
+        let commits = notes(NotesFilter::all().paths(vec!["src/**".into()], true))?;
+        assert_eq!(
+            (commits[0].note.total, commits[0].note.files.len()),
+            (60, 1)
+        );
+        assert_eq!(commits[1].subject, "Third");
+
+        assert_eq!(
+            subjects(NotesFilter::all().revisions("base..HEAD".into()))?,
+            ["Second", "Third"]
+        );
+        assert_eq!(
+            subjects(NotesFilter::all().revisions("base".into()))?,
+            ["First"]
+        );
+        assert_eq!(
+            subjects(NotesFilter::all().revisions("side...HEAD".into()))?,
+            ["Second", "Side", "Third"]
+        );
+        assert_eq!(
+            subjects(NotesFilter::all().revisions("...side".into()))?,
+            ["Second", "Side", "Third"]
+        );
+        assert!(get_notes(
+            &repo.repo,
+            "test",
+            NotesFilter::all().revisions("nope".into())
+        )
+        .is_err());
+
+        assert_eq!(
+            subjects(NotesFilter::all().min_total(30))?,
+            ["First", "Second"]
+        );
+        assert_eq!(
+            subjects(
+                NotesFilter::all()
+                    .paths(vec!["src/**".into()], true)
+                    .min_total(30)
+            )?,
+            ["First"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_notes_filter_exclude_apps() -> Result<(), Box<dyn Error>> {
+        let mut repo = TempRepo::new()?;
+        repo.commit("Mixed")?.note(
+            "[ver:1,total:100]
+src/main.ts:20,1585861200:20,m
+.gtm/terminal.app:80,1585861200:80,r",
+        )?;
+        repo.commit("Apps")?
+            .note("[ver:1,total:60]\n.gtm/terminal.app:60,1585861200:60,r")?;
+        repo.commit("Files")?
+            .note("[ver:1,total:40]\nsrc/lib.ts:40,1585861200:40,m")?;
+
+        assert_eq!(
+            repo.subjects(NotesFilter::all().min_total(30))?,
+            ["Apps", "Files", "Mixed"]
+        );
+        let commits = repo.commits(NotesFilter::all().exclude_apps())?;
+        assert_eq!(
+            commits
+                .iter()
+                .map(|c| (c.subject.as_str(), c.note.total, c.note.files.len()))
+                .collect::<Vec<_>>(),
+            [("Files", 40, 1), ("Mixed", 20, 1)]
+        );
+        assert_eq!(
+            repo.subjects(NotesFilter::all().exclude_apps().min_total(30))?,
+            ["Files"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_notes_days_around_midnight() -> Result<(), Box<dyn Error>> {
+        let repo = TempRepo::new()?;
+        let mut parents = Vec::new();
+        for (subject, seconds, offset) in &[
+            ("A", 1585873800, 0),    // 2020-04-03 00:30 UTC
+            ("B", 1585870200, 120),  // 2020-04-03 01:30 +02:00
+            ("C", 1585958399, -300), // 2020-04-03 18:59:59 -05:00
+            ("D", 1585958400, 0),    // 2020-04-04 00:00 UTC
+        ] {
+            let sig = Signature::new(
+                "Test Repo",
+                "test@repo.io",
+                &git2::Time::new(*seconds, *offset),
+            )?;
+            let oid = repo.commit_on("HEAD", &sig, &sig, subject, &parents)?;
+            oid.note(MINUTE_NOTE)?;
+            parents = vec![oid.0];
+        }
+
+        let subjects = |filter: &NotesFilter| repo.subjects(filter);
+        let day = |d| Some(NaiveDate::from_ymd(2020, 4, d));
+        let madrid = DateZone::Named(chrono_tz::Europe::Madrid);
+
+        assert_eq!(
+            subjects(NotesFilter::all().days(day(3), day(3), DateZone::Utc))?,
+            ["A", "C"]
+        );
+        assert_eq!(
+            subjects(NotesFilter::all().days(day(3), day(4), DateZone::Utc))?,
+            ["A", "C", "D"]
+        );
+        assert_eq!(
+            subjects(NotesFilter::all().days(None, day(2), DateZone::Utc))?,
+            ["B"]
+        );
+        assert_eq!(
+            subjects(NotesFilter::all().days(day(3), day(3), madrid))?,
+            ["A", "B"]
+        );
+        assert_eq!(
+            subjects(NotesFilter::all().days(day(4), None, madrid))?,
+            ["C", "D"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_notes_by_activity() -> Result<(), Box<dyn Error>> {
+        let mut repo = TempRepo::new()?;
+        repo.commit("Friday and Monday")?.note(
+            "[ver:1,total:220]
+src/main.ts:160,1585861200:60,1585872000:40,1585954800:60,m
+src/lib.ts:60,1586131200:60,r",
+        )?;
+        repo.commit("Monday")?
+            .note("[ver:1,total:30]\nsrc/lib.ts:30,1586134800:30,r")?;
+        repo.commit("Header total")?
+            .note("[ver:1,total:90]\nsrc/lib.ts:30,1586134800:30,r")?;
+
+        let day = |d| Some(NaiveDate::from_ymd(2020, 4, d));
+        let mut filter = NotesFilter::all();
+        filter.days(day(3), day(3), DateZone::Utc);
+        assert_eq!(get_notes(&repo.repo, "test", &filter)?.count(), 0);
+
+        filter.by_activity();
+        let notes = get_notes(&repo.repo, "test", &filter)?.collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(notes.len(), 1);
+        let note = &notes[0].commit.note;
+        assert_eq!(notes[0].commit.subject, "Friday and Monday");
+        assert_eq!(note.total, 100);
+        assert_eq!(note.files.len(), 1);
+        assert_eq!(
+            note.files[0].timeline,
+            btreemap! { 1585872000 => 40, 1585954800 => 60 }
+        );
+
+        let totals: Vec<_> = repo
+            .commits(NotesFilter::all().by_activity())?
+            .into_iter()
+            .map(|c| (c.subject, c.note.total))
+            .collect();
+        assert_eq!(
+            totals,
+            [
+                ("Friday and Monday".to_owned(), 220),
+                ("Header total".to_owned(), 90),
+                ("Monday".to_owned(), 30)
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_commit_identity() -> Result<(), Box<dyn Error>> {
+        let repo = TempRepo::new()?;
+        std::fs::write(
+            repo.repo.workdir().unwrap().join(".mailmap"),
+            "Alex Doe <alex@doe.io> <alex@old.io>\n",
+        )?;
+        let author = Signature::now("alex", "alex@old.io")?;
+        repo.commit_on("HEAD", &author, &repo.sig, "Message", &[])?
+            .note(MINUTE_NOTE)?;
+
+        let mut filter = NotesFilter::all();
+        filter.author("Alex Doe".into());
+        let notes = get_notes(&repo.repo, "test", &filter)?.collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(notes.len(), 1);
+        let commit = &notes[0].commit;
+        assert_eq!(commit.author, "alex");
+        assert_eq!(commit.author_email, "alex@old.io");
+        assert_eq!(commit.committer, "Test Repo");
+        assert_eq!(commit.committer_email, "test@repo.io");
+        assert_eq!(commit.identity, "Alex Doe <alex@doe.io>");
+        assert_eq!(
+            Commit::new(&notes[0].git_commit, "test".into(), CommitNote::new(1, 0)).identity,
+            ""
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_commit_graph() -> Result<(), Box<dyn Error>> {
+        let mut repo = TempRepo::new()?;
+        let c1 = repo.commit("C1")?.0;
+        let main = repo.repo.head()?.shorthand().unwrap().to_owned();
+        let f1 = repo
+            .commit_on("refs/heads/feature", &repo.sig, &repo.sig, "F1", &[c1])?
+            .0;
+        let c2 = repo.commit("C2")?.0;
+        let merge = repo
+            .commit_on("HEAD", &repo.sig, &repo.sig, "Merge", &[c2, f1])?
+            .0;
+        repo.repo
+            .tag_lightweight("v1", &repo.repo.find_object(merge, None)?, false)?;
+        for oid in &[c1, f1, merge] {
+            TempOid(*oid, &repo.repo, &repo.sig).note(MINUTE_NOTE)?;
+        }
+
+        let filter = NotesFilter::all();
+        for note in get_notes(&repo.repo, "test", &filter)? {
+            assert_eq!(note?.commit.graph, None);
+        }
+
+        let mut filter = NotesFilter::all();
+        filter.graph(Some(main.clone()));
+        let graphs: Vec<_> = repo
+            .commits(&filter)?
+            .into_iter()
+            .map(|c| c.graph.unwrap())
+            .collect();
+        let mut branches = vec!["feature".to_owned(), main.clone()];
+        branches.sort();
+
+        assert_eq!(
+            graphs[0],
+            CommitGraph {
+                parents: vec![],
+                merge: false,
+                branches: branches.clone(),
+                tags: vec!["v1".to_owned()],
+                base_distance: Some(2),
+            }
+        );
+        assert_eq!(
+            graphs[1],
+            CommitGraph {
+                parents: vec![c1.to_string()],
+                merge: false,
+                branches,
+                tags: vec!["v1".to_owned()],
+                base_distance: None,
+            }
+        );
+        assert_eq!(
+            graphs[2],
+            CommitGraph {
+                parents: vec![c2.to_string(), f1.to_string()],
+                merge: true,
+                branches: vec![main],
+                tags: vec!["v1".to_owned()],
+                base_distance: Some(0),
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_commit_graph_with_many_tags() -> Result<(), Box<dyn Error>> {
+        let mut repo = TempRepo::new()?;
+        let first = repo.commit("First")?;
+        first.note(MINUTE_NOTE)?;
+        let first = first.0;
+        let second = repo.commit("Second")?;
+        second.note(MINUTE_NOTE)?;
+        let second = second.0;
+        let mut names = Vec::new();
+        for i in 0..70 {
+            let oid = if i % 2 == 0 { first } else { second };
+            let name = format!("t{:02}", i);
+            repo.repo
+                .tag_lightweight(&name, &repo.repo.find_object(oid, None)?, false)?;
+            names.push(name);
+        }
+
+        let mut filter = NotesFilter::all();
+        filter.graph(None);
+        let tags: Vec<_> = repo
+            .commits(&filter)?
+            .into_iter()
+            .map(|c| c.graph.unwrap().tags)
+            .collect();
+        assert_eq!(tags[0], names);
+        assert_eq!(
+            tags[1],
+            names.iter().skip(1).step_by(2).cloned().collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_owned_commits_outlive_repo() -> Result<(), Box<dyn Error>> {
+        let commits = {
+            let mut repo = TempRepo::new()?;
+            repo.commit("Message")?
+                .note("[ver:2,total:90]\ntext/src/char.ts:90,1585918800:90,r")?;
+            let filter = NotesFilter::all();
+            let commits = get_notes(&repo.repo, "test", &filter)?
+                .map(|note| note.map(|note| note.commit))
+                .collect::<Result<Vec<Commit<'static>>, _>>()?;
+            commits
+        };
+        assert_eq!(commits.len(), 1);
+
+        let json = serde_json::to_vec(&commits)?;
+        let decoded: Vec<Commit<'static>> = serde_json::from_reader(&json[..])?;
+        assert_eq!(decoded[0].note, commits[0].note);
+        assert_eq!(decoded[0].note.files[0].source_file, "text/src/char.ts");
+        Ok(())
+    }
+
+    #[test]
+    fn test_notes_take_stops_early() -> Result<(), Box<dyn Error>> {
+        let mut repo = TempRepo::new()?;
+        for _ in 0..10 {
+            repo.commit("Message")?.note("[ver:1,total:0]")?;
+        }
+
+        let filter = NotesFilter::all();
+        assert_eq!(get_notes(&repo.repo, "test", &filter)?.take(3).count(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_corrupt_notes_are_reported() -> Result<(), Box<dyn Error>> {
+        let mut repo = TempRepo::new()?;
+        repo.commit("Valid")?.note("[ver:1,total:0]")?;
+        let corrupt = repo.commit("Corrupt")?;
+        corrupt.note("[ver:1,total:60]\nsrc/file.ts:60,1585918800:60,?")?;
+        let corrupt = corrupt.0;
+
+        let filter = NotesFilter::all();
+        let (ok, errs): (Vec<_>, Vec<_>) =
+            get_notes(&repo.repo, "test", &filter)?.partition(Result::is_ok);
+        assert_eq!(ok.len(), 1);
+        assert_eq!(errs.len(), 1);
+        match errs.into_iter().next().unwrap() {
+            Err(NoteError::Parse { oid, err }) => {
+                assert_eq!(oid, corrupt);
+                assert_eq!(
+                    err,
+                    CommitNoteParseError::InvalidFile {
+                        err: FileNoteParseError::StatusNotRecognized {
+                            got: "?".to_owned()
+                        },
+                        line: 2,
+                        span: 29..30,
+                        text: "src/file.ts:60,1585918800:60,?".to_owned(),
+                    }
+                );
+            }
+            other => panic!("Expected parse error, got: {:?}", other),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_notes_of_missing_commits_are_reported() -> Result<(), Box<dyn Error>> {
+        let mut repo = TempRepo::new()?;
+        repo.commit("Valid")?.note("[ver:1,total:0]")?;
+        let missing = Oid::from_str("0123456789abcdef0123456789abcdef01234567")?;
+        repo.repo.note(
+            &repo.sig,
+            &repo.sig,
+            Some(GTM_REFS),
+            missing,
+            "[ver:1,total:0]",
+            false,
+        )?;
+
+        let filter = NotesFilter::all();
+        let (ok, errs): (Vec<_>, Vec<_>) =
+            get_notes(&repo.repo, "test", &filter)?.partition(Result::is_ok);
+        assert_eq!(ok.len(), 1);
+        match errs.into_iter().next() {
+            Some(Err(err @ NoteError::MissingCommit { .. })) => {
+                assert_eq!(err.oid(), Some(missing))
+            }
+            other => panic!("Expected missing commit, got: {:?}", other),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_notes_with_unknown_version() -> Result<(), Box<dyn Error>> {
+        let mut repo = TempRepo::new()?;
+        repo.commit("Future")?
+            .note("[ver:9,total:60]\nsrc/file.ts:60,1585918800:60,r")?;
+
+        let mut filter = NotesFilter::all();
+        match get_notes(&repo.repo, "test", &filter)?.next() {
+            Some(Err(NoteError::Parse { err, .. })) => {
+                assert_eq!(err, CommitNoteParseError::UnknownVersion { version: 9 })
+            }
+            other => panic!("Expected unknown version, got: {:?}", other),
+        }
+
+        let mut versions = NoteVersions::default();
+        versions.register(9, PlainPathsDecoder);
+        filter.versions(versions);
+        let notes = get_notes(&repo.repo, "test", &filter)?.collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].commit.note.version, 9);
+        assert_eq!(notes[0].commit.note.files[0].source_file, "src/file.ts");
+        Ok(())
+    }
+
+    #[test]
+    fn test_lenient_notes_recover_valid_files() -> Result<(), Box<dyn Error>> {
+        let mut repo = TempRepo::new()?;
+        repo.commit("Corrupt")?.note(
+            "[ver:1,total:150]
+src/file.ts:90,1585918800:90,?
+src/main.ts:60,1585918800:60,r
+",
+        )?;
+
+        let mut filter = NotesFilter::all();
+        assert!(get_notes(&repo.repo, "test", &filter)?
+            .next()
+            .unwrap()
+            .is_err());
+
+        let notes =
+            get_notes(&repo.repo, "test", filter.lenient())?.collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(notes.len(), 1);
+        assert_eq!(
+            notes[0]
+                .diagnostics
+                .iter()
+                .map(|d| d.line())
+                .collect::<Vec<_>>(),
+            vec![2]
+        );
+        let commit = &notes[0].commit;
+        assert_eq!(commit.note.total, 60);
+        assert_eq!(commit.note.files.len(), 1);
+        assert_eq!(commit.note.files[0].source_file, "src/main.ts");
+        Ok(())
+    }
+
+    fn write_events(repo: &Repository, events: &[(&str, &str)]) -> Result<(), Box<dyn Error>> {
+        let gtm_dir = repo.workdir().unwrap().join(".gtm");
+        std::fs::create_dir_all(&gtm_dir)?;
+        for (name, file) in events {
+            std::fs::write(gtm_dir.join(name), file)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_commit_pending_note() -> Result<(), Box<dyn Error>> {
+        let mut repo = TempRepo::new()?;
+        repo.repo.config()?.set_str("user.name", "Test Repo")?;
+        repo.repo.config()?.set_str("user.email", "test@repo.io")?;
+        let head = repo.commit("Message")?;
+        head.note("[ver:1,total:60]\nsrc/main.ts:60,1585861200:60,m")?;
+        let head = head.0;
+
+        let (note, skipped) = commit_pending_note(&repo.repo, TimelineConfig::default())?;
+        assert_eq!(note, None);
+        assert!(skipped.is_empty());
+
+        write_events(
+            &repo.repo,
+            &[
+                ("1585864810.event", "src/main.ts"),
+                ("1585864830.event", "src/lib.ts"),
+                ("1585864900.event", "src/lib.ts"),
+                ("x.event", "src/lib.ts"),
+            ],
+        )?;
+        let gtm_dir = repo.repo.workdir().unwrap().join(".gtm");
+        let archive = gtm_dir.join("archive").join(head.to_string());
+        std::fs::create_dir_all(archive.parent().unwrap())?;
+        std::fs::write(&archive, "")?;
+        let filter = NotesFilter::all();
+        assert!(commit_pending_note(&repo.repo, TimelineConfig::default()).is_err());
+        assert!(gtm_dir.join("1585864810.event").exists());
+        let notes = get_notes(&repo.repo, "test", &filter)?.collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(notes[0].commit.note.total, 60);
+        std::fs::remove_file(&archive)?;
+
+        let (note, skipped) = commit_pending_note(&repo.repo, TimelineConfig::default())?;
+        let note = note.unwrap();
+        assert_eq!(note.total, 180);
+        assert_eq!(
+            skipped.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            vec![format!(
+                "Invalid event file name: {:?}",
+                gtm_dir.join("x.event")
+            )]
+        );
+
+        let notes = get_notes(&repo.repo, "test", &filter)?.collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].commit.note, note);
+        let main = note
+            .files
+            .iter()
+            .find(|f| f.source_file == "src/main.ts")
+            .unwrap();
+        assert_eq!(main.time_spent, 90);
+        assert_eq!(
+            main.timeline,
+            btreemap! { 1585861200 => 60, 1585864800 => 30 }
+        );
+        assert_eq!(main.status, FileStatus::Modified);
+        let lib = note
+            .files
+            .iter()
+            .find(|f| f.source_file == "src/lib.ts")
+            .unwrap();
+        assert_eq!(lib.time_spent, 90);
+
+        assert!(!gtm_dir.join("1585864810.event").exists());
+        assert!(archive.join("1585864810.event").exists());
+        assert!(gtm_dir.join("x.event").exists());
+        assert_eq!(
+            commit_pending_note(&repo.repo, TimelineConfig::default())?.0,
+            None
+        );
+
+        write_events(&repo.repo, &[("1585864810.event", "src/new.ts")])?;
+        let note = commit_pending_note(&repo.repo, TimelineConfig::default())?.0;
+        assert_eq!(note.unwrap().total, 240);
+        assert_eq!(
+            std::fs::read_to_string(archive.join("1585864810.event"))?,
+            "src/main.ts"
+        );
+        assert_eq!(
+            std::fs::read_to_string(archive.join("1585864810.event.1"))?,
+            "src/new.ts"
+        );
+        Ok(())
+    }
+
+    #[test]
+    #[cfg_attr(target_os = "windows", ignore)]
+    fn test_commit_pending_note_file_status() -> Result<(), Box<dyn Error>> {
+        let mut repo = TempRepo::new()?;
+        repo.repo.config()?.set_str("user.name", "Test Repo")?;
+        repo.repo.config()?.set_str("user.email", "test@repo.io")?;
+        let workdir = repo.repo.workdir().unwrap().to_owned();
+        for file in &["modified.ts", "deleted.ts", "read.ts"] {
+            std::fs::write(workdir.join(file), "committed")?;
+        }
+        let mut index = repo.repo.index()?;
+        index.add_all(&["*.ts"], git2::IndexAddOption::DEFAULT, None)?;
+        index.write()?;
+        repo.commit("Add files")?;
+
+        std::fs::write(workdir.join("modified.ts"), "changed")?;
+        std::fs::write(workdir.join("a,1:2"), "new")?;
+        index.add_path(Path::new("modified.ts"))?;
+        index.add_path(Path::new("a,1:2"))?;
+        index.remove_path(Path::new("deleted.ts"))?;
+        index.write()?;
+        repo.commit("Change files")?;
+        write_events(
+            &repo.repo,
+            &[
+                ("1585864810.event", "modified.ts"),
+                ("1585864810.1.event", "deleted.ts"),
+                ("1585864810.2.event", "read.ts"),
+                ("1585864810.3.event", "a,1:2"),
+            ],
+        )?;
+
+        let note = commit_pending_note(&repo.repo, TimelineConfig::default())?
+            .0
+            .unwrap();
+        assert_eq!(note.version, 3);
+        let file_status = |name: &str| {
+            note.files
+                .iter()
+                .find(|f| f.source_file == name)
+                .map(|f| f.status.clone())
+        };
+        assert_eq!(file_status("modified.ts"), Some(FileStatus::Modified));
+        assert_eq!(file_status("deleted.ts"), Some(FileStatus::Deleted));
+        assert_eq!(file_status("read.ts"), Some(FileStatus::Read));
+        assert_eq!(file_status("a,1:2"), Some(FileStatus::Modified));
+
+        let notes = repo.commits(&NotesFilter::all())?;
+        assert_eq!(notes[0].note, note);
+        Ok(())
+    }
+
+    #[test]
+    fn test_pending_note_timeline_config() -> Result<(), Box<dyn Error>> {
+        let mut repo = TempRepo::new()?;
+        repo.repo.config()?.set_str("user.name", "Test Repo")?;
+        repo.repo.config()?.set_str("user.email", "test@repo.io")?;
+        repo.commit("Message")?;
+        write_events(
+            &repo.repo,
+            &[
+                ("1585864810.event", "src/main.ts"),
+                ("1585864930.event", "src/main.ts"),
+            ],
+        )?;
+        let workdir = repo.repo.workdir().unwrap().to_owned();
+        let config = TimelineConfig {
+            bin_width: 60,
+            credit: 30,
+            idle_timeout: Some(120),
+        };
+
+        let status = workdir_status(&workdir, AppTime::Include, config);
+        assert_eq!(status.commit_note.total, 150);
+        assert_eq!(status.warnings, Vec::<String>::new());
+        let config = TimelineConfig {
+            bin_width: 0,
+            ..config
+        };
+        assert_eq!(
+            workdir_status(&workdir, AppTime::Include, config)
+                .warnings
+                .len(),
+            1
+        );
+        assert!(commit_pending_note(&repo.repo, config).is_err());
+
+        let config = TimelineConfig {
+            credit: 30,
+            ..TimelineConfig::default()
+        };
+        let note = commit_pending_note(&repo.repo, config)?.0.unwrap();
+        assert_eq!(note.total, 60);
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_event_files_are_reported() -> Result<(), Box<dyn Error>> {
+        let repo = TempRepo::new()?;
+        write_events(
+            &repo.repo,
+            &[
+                ("1585864810.event", "src/main.ts"),
+                ("1585864820.event", ""),
+            ],
+        )?;
+        let workdir = repo.repo.workdir().unwrap();
+
+        let (events, errors) = read_event_files_lenient(workdir)?;
+        assert_eq!(events.len(), 1);
+        assert_eq!(
+            errors.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            vec![format!(
+                "Empty event file: {:?}",
+                workdir.join(".gtm/1585864820.event")
+            )]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_workdir_status_skips_malformed_events() -> Result<(), Box<dyn Error>> {
+        let healthy = TempRepo::new()?;
+        write_events(&healthy.repo, &[("1585864810.event", "src/main.ts")])?;
+        let malformed = TempRepo::new()?;
+        write_events(
+            &malformed.repo,
+            &[
+                ("1585864810.event", "src/lib.ts"),
+                ("foo", "stray file"),
+                ("bar.event", "src/lib.ts"),
+            ],
+        )?;
+        std::fs::write(
+            malformed
+                .repo
+                .workdir()
+                .unwrap()
+                .join(".gtm/1585864830.event"),
+            b"\xff\xfe",
+        )?;
+        let gtm_dir = malformed.repo.workdir().unwrap().join(".gtm");
+        match read_event_files(malformed.repo.workdir().unwrap()) {
+            Err(EventFileError::InvalidFileName(path)) => {
+                assert_eq!(path, gtm_dir.join("bar.event"))
+            }
+            other => panic!("Expected invalid file name, got: {:?}", other),
+        }
+        let empty = TempRepo::new()?;
+        let projects = [
+            healthy.repo.workdir(),
+            malformed.repo.workdir(),
+            empty.repo.workdir(),
+        ];
+
+        let mut out = Vec::new();
+        write_workdir_status(
+            &mut out,
+            projects.iter().map(|p| p.unwrap()),
+            AppTime::Include,
+            TimelineConfig::default(),
+        );
+        let status: serde_json::Value = serde_json::from_slice(&out)?;
+
+        let healthy = &status[healthy.repo.workdir().unwrap().key()];
+        assert_eq!(healthy["Total"], 60);
+        assert_eq!(healthy.get("Warnings"), None);
+        let malformed = &status[malformed.repo.workdir().unwrap().key()];
+        assert_eq!(malformed["Total"], 60);
+        assert_eq!(
+            malformed["CommitNote"]["Files"][0]["SourceFile"],
+            "src/lib.ts"
+        );
+        assert_eq!(malformed["Warnings"].as_array().unwrap().len(), 2);
+        let empty = &status[empty.repo.workdir().unwrap().key()];
+        assert_eq!(empty["Total"], 0);
+        assert_eq!(empty["ShortId"], serde_json::Value::Null);
+        assert_eq!(empty["Label"], empty["Branch"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_head_status() -> Result<(), Box<dyn Error>> {
+        let mut repo = TempRepo::new()?;
+        let status = head_status(&repo.repo)?;
+        assert!(status.branch.is_some());
+        assert_eq!(status.short_id, None);
+
+        let first = repo.commit("First")?.0;
+        repo.repo
+            .branch("base", &repo.repo.find_commit(first)?, false)?;
+        repo.commit("Second")?;
+        let third = repo.commit("Third\n\nBody")?.0;
+        let status = head_status(&repo.repo)?;
+        let branch = status.branch.clone().unwrap();
+        assert_eq!(status.subject.as_deref(), Some("Third"));
+        assert_eq!(status.ahead, None);
+        assert_eq!(status.to_string(), format!("{}: Third", branch));
+
+        repo.repo
+            .find_branch(&branch, git2::BranchType::Local)?
+            .set_upstream(Some("base"))?;
+        let status = head_status(&repo.repo)?;
+        assert_eq!((status.ahead, status.behind), (Some(2), Some(0)));
+        assert_eq!(status.to_string(), format!("{}: Third [ahead 2]", branch));
+
+        repo.repo.set_head_detached(third)?;
+        let status = head_status(&repo.repo)?;
+        let short_id = status.short_id.clone().unwrap();
+        assert_eq!(status.branch, None);
+        assert!(third.to_string().starts_with(&short_id));
+        assert_eq!(
+            status.to_string(),
+            format!("{} (detached): Third", short_id)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_workdir_status_file_status() -> Result<(), Box<dyn Error>> {
+        let mut repo = TempRepo::new()?;
+        let workdir = repo.repo.workdir().unwrap().to_owned();
+        for file in &["modified.ts", "deleted.ts", "read.ts"] {
+            std::fs::write(workdir.join(file), "committed")?;
+        }
+        let mut index = repo.repo.index()?;
+        index.add_all(&["*.ts"], git2::IndexAddOption::DEFAULT, None)?;
+        index.write()?;
+        repo.commit("Add files")?;
+
+        std::fs::write(workdir.join("modified.ts"), "changed")?;
+        std::fs::remove_file(workdir.join("deleted.ts"))?;
+        std::fs::write(workdir.join("staged.ts"), "new")?;
+        index.add_path(Path::new("staged.ts"))?;
+        index.write()?;
+        write_events(
+            &repo.repo,
+            &[
+                ("1585864810.event", "modified.ts"),
+                ("1585864810.1.event", "deleted.ts"),
+                ("1585864810.2.event", "read.ts"),
+                ("1585864810.3.event", "staged.ts"),
+            ],
+        )?;
+
+        let status = workdir_status(&workdir, AppTime::Include, TimelineConfig::default());
+        assert_eq!(status.warnings, Vec::<String>::new());
+        let file_status = |name: &str| {
+            status
+                .commit_note
+                .files
+                .iter()
+                .find(|f| f.source_file == name)
+                .map(|f| f.status.clone())
+        };
+        assert_eq!(file_status("modified.ts"), Some(FileStatus::Modified));
+        assert_eq!(file_status("deleted.ts"), Some(FileStatus::Deleted));
+        assert_eq!(file_status("read.ts"), Some(FileStatus::Read));
+        assert_eq!(file_status("staged.ts"), Some(FileStatus::Modified));
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_record_event_in_symlinked_project() -> Result<(), Box<dyn Error>> {
+        let repo = TempRepo::new()?;
+        let workdir = repo.repo.workdir().unwrap().canonicalize()?;
+        let file = workdir.join("main.ts");
+        std::fs::write(&file, "")?;
+        let links = tempdir()?;
+        let link = links.path().join("project");
+        std::os::unix::fs::symlink(&workdir, &link)?;
+
+        let mut config = tempfile::NamedTempFile::new()?;
+        serde_json::to_writer(&mut config, &hashmap! { &link => "" })?;
+        let projects = Projects::from_file(config.path())?;
+        assert_eq!(
+            record_event(&projects, &file, 1585864810)?,
+            Some(workdir.join(".gtm/1585864810.event"))
+        );
+        assert_eq!(
+            record_event(&projects, &link.join("main.ts"), 1585864811)?,
+            Some(workdir.join(".gtm/1585864811.event"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_record_event() -> Result<(), Box<dyn Error>> {
+        let repo = TempRepo::new()?;
+        let workdir = repo.repo.workdir().unwrap().canonicalize()?;
+        std::fs::create_dir(workdir.join("src"))?;
+        let file = workdir.join("src/main.ts");
+        std::fs::write(&file, "")?;
+
+        let mut config = tempfile::NamedTempFile::new()?;
+        config.write_all(b"{}")?;
+        match record_event(&Projects::from_file(config.path())?, &file, 1585864810) {
+            Err(RecordError::NotInitialized(path)) => assert_eq!(path, workdir),
+            other => panic!("Expected not initialized, got: {:?}", other),
+        }
+
+        let mut config = tempfile::NamedTempFile::new()?;
+        serde_json::to_writer(&mut config, &hashmap! { &workdir => "" })?;
+        let projects = Projects::from_file(config.path())?;
+        let event = record_event(&projects, &file, 1585864810)?;
+        assert_eq!(event, Some(workdir.join(".gtm/1585864810.event")));
+        assert_eq!(record_event(&projects, &file, 1585864810)?, None);
+        assert!(record_event(&projects, &file, 1585864811)?.is_some());
+        let deleted = workdir.join("src/deleted.ts");
+        assert_eq!(
+            record_event(&projects, &deleted, 1585864810)?,
+            Some(workdir.join(".gtm/1585864810.1.event"))
+        );
+
+        let events = read_event_files(&workdir)?;
+        assert_eq!(
+            events
+                .iter()
+                .map(|(_, event)| (event.timestamp, event.filename()))
+                .collect::<Vec<_>>(),
+            vec![
+                (1585864810, "src/main.ts"),
+                (1585864810, "src/deleted.ts"),
+                (1585864811, "src/main.ts"),
+            ]
+        );
+        assert_eq!(
+            std::fs::read_to_string(workdir.join(".gtm/1585864810.event"))?,
+            "src/main.ts"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_record_app_event() -> Result<(), Box<dyn Error>> {
+        let repo = TempRepo::new()?;
+        let workdir = repo.repo.workdir().unwrap().canonicalize()?;
+        let file = workdir.join("main.ts");
+        std::fs::write(&file, "")?;
+        let mut config = tempfile::NamedTempFile::new()?;
+        serde_json::to_writer(&mut config, &hashmap! { &workdir => "" })?;
+        let projects = Projects::from_file(config.path())?;
+
+        for name in &["../terminal", "", ".", "..", "-_."] {
+            match record_app_event(&projects, &workdir, name, 1585864810) {
+                Err(RecordError::InvalidApp(app)) => assert_eq!(app, *name),
+                other => panic!("Expected invalid app, got: {:?}", other),
+            }
+        }
+        record_app_event(&projects, &workdir, "terminal", 1585864810)?;
+        record_event(&projects, &file, 1585864810)?;
+        let events = read_event_files(&workdir)?;
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].1.filename(), ".gtm/terminal.app");
+
+        let status = workdir_status(&workdir, AppTime::Include, TimelineConfig::default());
+        assert_eq!(
+            (status.total, status.app_time, status.file_time),
+            (60, 30, 30)
+        );
+        let terminal = &status.commit_note.files[0];
+        assert_eq!(terminal.app(), Some("terminal"));
+        assert_eq!(terminal.status, FileStatus::Read);
+
+        let status = workdir_status(&workdir, AppTime::Exclude, TimelineConfig::default());
+        assert_eq!(
+            (status.total, status.app_time, status.file_time),
+            (30, 0, 30)
+        );
+        assert_eq!(status.commit_note.files.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_watch_records_file_events() -> Result<(), Box<dyn Error>> {
+        let repo = TempRepo::new()?;
+        let workdir = repo.repo.workdir().unwrap().to_owned();
+        std::fs::create_dir(workdir.join("src"))?;
+        std::fs::create_dir(workdir.join("target"))?;
+        std::fs::write(workdir.join(".gitignore"), "target/\n*.log\n")?;
+        std::fs::create_dir(workdir.join("lib"))?;
+        std::fs::write(workdir.join("src/main.ts"), "")?;
+        std::fs::write(workdir.join("lib/lib.ts"), "")?;
+        let mut index = repo.repo.index()?;
+        index.add_path(Path::new("src/main.ts"))?;
+        index.add_path(Path::new("lib/lib.ts"))?;
+        index.write()?;
+        std::fs::remove_dir_all(workdir.join("lib"))?;
+
+        let mut watcher = gtm::watch::Watcher::new()?;
+        watcher.add_project(&workdir)?;
+        std::fs::write(workdir.join("src/untracked.ts"), "untracked")?;
+        std::fs::write(workdir.join("src/main.ts"), "main")?;
+        std::fs::write(workdir.join("src/debug.log"), "ignored")?;
+        std::fs::write(workdir.join("target/out.js"), "ignored")?;
+        std::fs::write(workdir.join(".git/description"), "skipped")?;
+        std::fs::create_dir(workdir.join("gone"))?;
+        std::fs::remove_dir(workdir.join("gone"))?;
+        std::fs::create_dir(workdir.join("lib"))?;
+        std::fs::write(workdir.join("lib/lib.ts"), "lib")?;
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        std::thread::spawn(move || loop {
+            if sender.send(watcher.record_events()).is_err() {
+                break;
+            }
+        });
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+        let mut recorded = Vec::new();
+        while recorded.len() < 2 {
+            let timeout = deadline.saturating_duration_since(std::time::Instant::now());
+            recorded.extend(
+                receiver
+                    .recv_timeout(timeout)??
+                    .into_iter()
+                    .map(|(_, event)| event.filename().to_owned()),
+            );
+        }
+        recorded.sort();
+        assert_eq!(recorded, vec!["lib/lib.ts", "src/main.ts"]);
+
+        let events = read_event_files(&workdir)?;
+        let mut files: Vec<_> = events.iter().map(|(_, e)| e.filename()).collect();
+        files.sort();
+        assert_eq!(files, vec!["lib/lib.ts", "src/main.ts"]);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_watch_skips_missing_projects() -> Result<(), Box<dyn Error>> {
+        let repo = TempRepo::new()?;
+        let workdir = repo.repo.workdir().unwrap().to_owned();
+        std::fs::write(workdir.join("main.ts"), "")?;
+        let mut index = repo.repo.index()?;
+        index.add_path(Path::new("main.ts"))?;
+        index.write()?;
+        let missing = workdir.join("missing");
+
+        let mut config = tempfile::NamedTempFile::new()?;
+        serde_json::to_writer(&mut config, &hashmap! { &missing => "", &workdir => "" })?;
+        let mut watcher = gtm::watch::Watcher::from_projects(&Projects::from_file(config.path())?)?;
+        std::fs::write(workdir.join("main.ts"), "main")?;
+        let recorded = watcher.record_events()?;
+        assert_eq!(
+            recorded
+                .iter()
+                .map(|(_, event)| event.filename())
+                .collect::<Vec<_>>(),
+            vec!["main.ts"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_notes() -> Result<(), Box<dyn Error>> {
+        let mut repo = TempRepo::new()?;
+        repo.commit("Valid")?
+            .note("[ver:1,total:60]\nsrc/file.ts:60,1585918800:60,r")?;
+        let corrupt = repo.commit("Corrupt")?;
+        corrupt.note("[ver:1,total:60]\nsrc/file.ts:60,1585918800:60,?")?;
+        let corrupt = corrupt.0;
+        let missing = Oid::from_str("0123456789abcdef0123456789abcdef01234567")?;
+        repo.repo.note(
+            &repo.sig,
+            &repo.sig,
+            Some(GTM_REFS),
+            missing,
+            "[ver:1,total:0]",
+            false,
+        )?;
+
+        let mut issues: Vec<_> = check_notes(&repo.repo, "test")?
+            .into_iter()
+            .map(|issue| (issue.commit, issue.problem))
+            .collect();
+        issues.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut expected = vec![
+            (
+                corrupt.to_string(),
+                Problem::InvalidNote {
+                    error: "line 2: status `?` not recognized, expected a letter such as `m`, `r` or `d`".to_owned(),
+                },
+            ),
+            (missing.to_string(), Problem::MissingCommit),
+        ];
+        expected.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(issues, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_notes_without_notes() -> Result<(), Box<dyn Error>> {
+        let mut repo = TempRepo::new()?;
+        repo.commit("Message")?;
+        assert_eq!(check_notes(&repo.repo, "test")?, vec![]);
+        Ok(())
+    }
+
     #[test]
     fn test_commit_message() -> Result<(), Box<dyn Error>> {
         let mut repo = TempRepo::new()?;
@@ -298,7 +1426,9 @@ mod cli_tests {
     use crate::init_projects_tests;
     use crate::init_projects_tests::PROJECT_JSON;
     use assert_cmd::Command;
-    use gtm::Commit;
+    use chrono::Utc;
+    use git2::{Repository, Signature};
+    use gtm::{projects::ProjectKey, services::PersonTime, Commit, GTM_REFS};
     use predicates::prelude::*;
     use std::collections::HashMap;
     use std::error::Error;
@@ -394,4 +1524,272 @@ mod cli_tests {
             .stderr(predicate::str::is_empty());
         Ok(())
     }
+
+    #[test]
+    #[cfg_attr(target_os = "windows", ignore)]
+    fn run_commits_with_time_zone() -> Result<(), Box<dyn Error>> {
+        let home = create_config_file(b"{}")?;
+        for tz in &["utc", "local", "America/New_York", "america/new_york"] {
+            Command::cargo_bin(GTM_CMD)?
+                .env("HOME", home.path())
+                .args(&["commits", "--from-date", "2020-04-03", "--tz", tz])
+                .assert()
+                .success();
+        }
+        Command::cargo_bin(GTM_CMD)?
+            .env("HOME", home.path())
+            .args(&["commits", "--tz", "Mars/Olympus_Mons"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("Mars/Olympus_Mons"));
+        Ok(())
+    }
+
+    #[test]
+    #[cfg_attr(target_os = "windows", ignore)]
+    fn run_people() -> Result<(), Box<dyn Error>> {
+        let workdir = tempdir()?;
+        let repo = Repository::init(workdir.path())?;
+        fs::write(
+            workdir.path().join(".mailmap"),
+            "Alex Doe <alex@doe.io> <alex@old.io>\n",
+        )?;
+        let tree = repo.find_tree(repo.index()?.write_tree()?)?;
+        let mut parents = Vec::new();
+        for (name, email, total) in &[
+            ("alex", "alex@old.io", 60),
+            ("Alex Doe", "alex@doe.io", 30),
+            ("Sam Roe", "sam@roe.io", 10),
+        ] {
+            let sig = Signature::now(name, email)?;
+            let parent: Vec<_> = parents.iter().collect();
+            let oid = repo.commit(Some("HEAD"), &sig, &sig, "Message", &tree, &parent)?;
+            let note = format!("[ver:1,total:{0}]\nsrc/main.ts:{0},1585861200:{0},m", total);
+            repo.note(&sig, &sig, Some(GTM_REFS), oid, &note, false)?;
+            parents = vec![repo.find_commit(oid)?];
+        }
+
+        let json = serde_json::to_vec(&hashmap! { workdir.path() => "" })?;
+        let home = create_config_file(&json)?;
+        Command::cargo_bin(GTM_CMD)?
+            .env("HOME", home.path())
+            .arg("people")
+            .assert()
+            .success()
+            .stdout(predicate::function(|out| {
+                let people: Vec<PersonTime> = serde_json::from_slice(out).unwrap();
+                people
+                    == [
+                        PersonTime {
+                            identity: "Alex Doe <alex@doe.io>".to_owned(),
+                            total: 90,
+                            commits: 2,
+                        },
+                        PersonTime {
+                            identity: "Sam Roe <sam@roe.io>".to_owned(),
+                            total: 10,
+                            commits: 1,
+                        },
+                    ]
+            }));
+        Ok(())
+    }
+
+    #[test]
+    #[cfg_attr(target_os = "windows", ignore)]
+    fn run_fsck_with_empty_json() -> Result<(), Box<dyn Error>> {
+        let home = create_config_file(b"{}")?;
+        Command::cargo_bin(GTM_CMD)?
+            .env("HOME", home.path())
+            .arg("fsck")
+            .assert()
+            .success()
+            .stdout(predicate::str::is_empty());
+        Ok(())
+    }
+
+    #[test]
+    #[cfg_attr(target_os = "windows", ignore)]
+    fn run_fsck_with_corrupt_note() -> Result<(), Box<dyn Error>> {
+        let workdir = tempdir()?;
+        let repo = Repository::init(workdir.path())?;
+        let sig = Signature::now("Test Repo", "test@repo.io")?;
+        let tree = repo.find_tree(repo.index()?.write_tree()?)?;
+        let oid = repo.commit(Some("HEAD"), &sig, &sig, "Message", &tree, &[])?;
+        repo.note(&sig, &sig, Some(GTM_REFS), oid, "[ver:1,total:60]", false)?;
+
+        let json = serde_json::to_vec(&hashmap! { workdir.path() => "" })?;
+        let home = create_config_file(&json)?;
+        Command::cargo_bin(GTM_CMD)?
+            .env("HOME", home.path())
+            .args(&["fsck", "--json"])
+            .assert()
+            .failure()
+            .stdout(predicate::function(|out| {
+                let issues: Vec<serde_json::Value> = serde_json::from_slice(out).unwrap();
+                issues.len() == 1
+                    && issues[0]["Commit"] == oid.to_string()
+                    && issues[0]["Problem"]["Kind"] == "TotalMismatch"
+            }));
+        Ok(())
+    }
+
+    #[test]
+    #[cfg_attr(target_os = "windows", ignore)]
+    fn run_fsck_with_missing_repository() -> Result<(), Box<dyn Error>> {
+        let workdir = tempdir()?;
+        let repo = Repository::init(workdir.path())?;
+        let sig = Signature::now("Test Repo", "test@repo.io")?;
+        let tree = repo.find_tree(repo.index()?.write_tree()?)?;
+        let oid = repo.commit(Some("HEAD"), &sig, &sig, "Message", &tree, &[])?;
+        repo.note(&sig, &sig, Some(GTM_REFS), oid, "[ver:1,total:60]", false)?;
+        let missing = tempdir()?.path().join("moved");
+
+        let json = serde_json::to_vec(&hashmap! { workdir.path() => "", missing.as_path() => "" })?;
+        let home = create_config_file(&json)?;
+        Command::cargo_bin(GTM_CMD)?
+            .env("HOME", home.path())
+            .args(&["fsck", "--json"])
+            .assert()
+            .failure()
+            .stdout(predicate::function(|out| {
+                let issues: Vec<serde_json::Value> = serde_json::from_slice(out).unwrap();
+                let kinds: Vec<_> = issues
+                    .iter()
+                    .map(|issue| (issue["Project"].as_str(), issue["Problem"]["Kind"].as_str()))
+                    .collect();
+                kinds.len() == 2
+                    && kinds.contains(&(Some("moved"), Some("MissingRepository")))
+                    && kinds.contains(&(Some(workdir.path().key()), Some("TotalMismatch")))
+            }));
+        Ok(())
+    }
+
+    #[test]
+    #[cfg_attr(target_os = "windows", ignore)]
+    fn run_commit_note() -> Result<(), Box<dyn Error>> {
+        let workdir = tempdir()?;
+        let repo = Repository::init(workdir.path())?;
+        repo.config()?.set_str("user.name", "Test Repo")?;
+        repo.config()?.set_str("user.email", "test@repo.io")?;
+        let sig = Signature::now("Test Repo", "test@repo.io")?;
+        fs::create_dir(workdir.path().join("src"))?;
+        fs::write(workdir.path().join("src/main.ts"), "")?;
+        let mut index = repo.index()?;
+        index.add_path(std::path::Path::new("src/main.ts"))?;
+        index.write()?;
+        let tree = repo.find_tree(index.write_tree()?)?;
+        let oid = repo.commit(Some("HEAD"), &sig, &sig, "Message", &tree, &[])?;
+        fs::create_dir(workdir.path().join(".gtm"))?;
+        fs::write(workdir.path().join(".gtm/1585864810.event"), "src/main.ts")?;
+        fs::write(
+            workdir.path().join(".gtm/1585864810.1.event"),
+            "src/util.ts",
+        )?;
+
+        Command::cargo_bin(GTM_CMD)?
+            .arg("commit-note")
+            .arg(workdir.path())
+            .assert()
+            .success()
+            .stdout(predicate::function(|out| {
+                let note: serde_json::Value = serde_json::from_slice(out).unwrap();
+                note["Total"] == 60 && note["Files"][0]["SourceFile"] == "src/main.ts"
+            }));
+        let note = repo.find_note(Some(GTM_REFS), oid)?;
+        assert_eq!(
+            note.message(),
+            Some(
+                "[ver:1,total:60]\nsrc/main.ts:30,1585864800:30,m\nsrc/util.ts:30,1585864800:30,r"
+            )
+        );
+
+        Command::cargo_bin(GTM_CMD)?
+            .arg("commit-note")
+            .arg(workdir.path())
+            .assert()
+            .success()
+            .stdout("null");
+        Ok(())
+    }
+
+    #[test]
+    #[cfg_attr(target_os = "windows", ignore)]
+    fn run_commit_note_with_timeline_flags() -> Result<(), Box<dyn Error>> {
+        let workdir = tempdir()?;
+        let repo = Repository::init(workdir.path())?;
+        repo.config()?.set_str("user.name", "Test Repo")?;
+        repo.config()?.set_str("user.email", "test@repo.io")?;
+        let sig = Signature::now("Test Repo", "test@repo.io")?;
+        let tree = repo.find_tree(repo.index()?.write_tree()?)?;
+        let oid = repo.commit(Some("HEAD"), &sig, &sig, "Message", &tree, &[])?;
+        fs::create_dir(workdir.path().join(".gtm"))?;
+        fs::write(workdir.path().join(".gtm/1585864810.event"), "src/main.ts")?;
+        fs::write(workdir.path().join(".gtm/1585864930.event"), "src/main.ts")?;
+
+        Command::cargo_bin(GTM_CMD)?
+            .arg("commit-note")
+            .arg(workdir.path())
+            .arg("--bin-width")
+            .arg("0")
+            .assert()
+            .failure();
+        Command::cargo_bin(GTM_CMD)?
+            .arg("commit-note")
+            .arg(workdir.path())
+            .args(&["--credit", "30", "--idle-timeout", "120"])
+            .assert()
+            .success();
+        let note = repo.find_note(Some(GTM_REFS), oid)?;
+        assert_eq!(
+            note.message(),
+            Some("[ver:1,total:150]\nsrc/main.ts:150,1585864800:150,r")
+        );
+        Ok(())
+    }
+
+    #[test]
+    #[cfg_attr(target_os = "windows", ignore)]
+    fn run_record() -> Result<(), Box<dyn Error>> {
+        let workdir = tempdir()?;
+        Repository::init(workdir.path())?;
+        fs::create_dir(workdir.path().join("src"))?;
+        let file = workdir.path().join("src/main.ts");
+        fs::write(&file, "")?;
+
+        let home = create_config_file(b"{}")?;
+        Command::cargo_bin(GTM_CMD)?
+            .env("HOME", home.path())
+            .arg("record")
+            .arg(&file)
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("not initialized"));
+
+        let json = serde_json::to_vec(&hashmap! { workdir.path().canonicalize()? => "" })?;
+        let home = create_config_file(&json)?;
+        let start = Utc::now().timestamp();
+        for file in &[file, workdir.path().join("src/deleted.ts")] {
+            Command::cargo_bin(GTM_CMD)?
+                .env("HOME", home.path())
+                .arg("record")
+                .arg(file)
+                .assert()
+                .success()
+                .stdout(predicate::str::is_empty());
+        }
+        let end = Utc::now().timestamp();
+
+        let events = gtm::status::read_event_files(workdir.path())?;
+        let mut filenames = events
+            .iter()
+            .map(|(_, event)| event.filename())
+            .collect::<Vec<_>>();
+        filenames.sort();
+        assert_eq!(filenames, vec!["src/deleted.ts", "src/main.ts"]);
+        assert!(events
+            .iter()
+            .all(|(_, event)| start <= event.timestamp && event.timestamp <= end));
+        Ok(())
+    }
 }